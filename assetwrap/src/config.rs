@@ -8,11 +8,12 @@ use color_eyre::{eyre::eyre, Result};
 use globset::Glob;
 use serde::Deserialize;
 
-use crate::loader::Loader;
+use crate::{loader::Loader, syntax::SyntaxThemes};
 
 #[derive(Deserialize)]
 pub struct AssetConfig {
     pub asset_paths: Vec<AssetPath>,
+    pub syntax_themes: Option<SyntaxThemes>,
 }
 
 #[derive(Deserialize)]
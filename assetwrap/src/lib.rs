@@ -12,6 +12,7 @@ use serde::{Deserialize, Serialize};
 
 pub mod config;
 pub mod loader;
+pub mod syntax;
 
 #[derive(Parser)]
 pub struct Cli {
@@ -8,20 +8,15 @@ use sha2::{Digest, Sha256};
 
 use crate::Asset;
 
-#[derive(Copy, Clone, Deserialize)]
+#[derive(Copy, Clone, Default, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Loader {
+    #[default]
     Default,
     Css,
     Image,
 }
 
-impl Default for Loader {
-    fn default() -> Self {
-        Self::Default
-    }
-}
-
 impl Loader {
     pub fn process(&self, input_path: &Path, hashed_name: bool) -> Result<Vec<Asset>> {
         match self {
@@ -64,13 +59,22 @@ impl Loader {
 
     fn load_css(input_path: &Path, hashed_name: bool) -> Result<Vec<Asset>> {
         let source_css = std::fs::read_to_string(input_path)?;
+        Self::load_css_source(input_path, &source_css, hashed_name)
+    }
+
+    /// Minifies a generated stylesheet, as if it had been read from `input_path`.
+    pub fn load_css_source(
+        input_path: &Path,
+        source_css: &str,
+        hashed_name: bool,
+    ) -> Result<Vec<Asset>> {
         let content = {
             let filename = input_path
                 .file_name()
                 .expect("file does not have a name")
                 .to_string_lossy()
                 .to_string();
-            Self::minify_css(filename, &source_css)?
+            Self::minify_css(filename, source_css)?
         };
 
         let asset = Self::create_asset(input_path, content, hashed_name, None)?;
@@ -84,6 +84,22 @@ fn main() -> Result<()> {
         }
     }
 
+    if let Some(themes) = &config.syntax_themes {
+        let source = themes.generate_css()?;
+        let assets = assetwrap::loader::Loader::load_css_source(&themes.path, &source, true)?;
+        let mut output_paths = vec![];
+        for asset in assets {
+            let (output_path, s) = asset.render(&themes.output)?;
+            let new_name = output_path.to_string_lossy().replace("./assets-gen/", "");
+            let s = ByteSize::b(s);
+            total_size += s;
+            println!("Rendered {} ({})", &new_name, s);
+            output_paths.push(new_name);
+        }
+        let original_name = themes.path.to_string_lossy().replace("./", "/");
+        asset_map.insert(original_name, output_paths);
+    }
+
     let mut index = File::create(
         std::env::var("ASSET_INDEX_OUT_PATH").unwrap_or_else(|_| "assetindex.json".to_string()),
     )?;
@@ -97,23 +113,23 @@ fn main() -> Result<()> {
         for output_paths in asset_map.values() {
             for output_path in output_paths {
                 // TODO: Don't hardcode this prefix, lol
-                let res = bucket.head_object(&output_path)?;
+                let res = bucket.head_object(output_path)?;
                 if res.1 == 404 {
                     // Doesn't exist, we need to upload
                     let fs_path = {
                         let mut path = PathBuf::new();
                         path.push("./assets-gen/");
-                        path.push(&output_path);
+                        path.push(output_path);
                         path
                     };
 
                     let content = std::fs::read(&fs_path)?;
-                    let content_type = mime_guess::from_path(&output_path)
+                    let content_type = mime_guess::from_path(output_path)
                         .first_or_text_plain()
                         .essence_str()
                         .to_owned();
                     bucket.put_object_with_content_type(
-                        &output_path,
+                        output_path,
                         &content[..],
                         &content_type,
                     )?;
@@ -0,0 +1,64 @@
+use std::path::PathBuf;
+
+use color_eyre::{eyre::eyre, Result};
+use serde::Deserialize;
+use syntect::{
+    highlighting::{Color, Theme, ThemeSet},
+    html::{css_for_theme_with_class_style, ClassStyle},
+};
+
+/// Prefix of the classes on highlighted code, which must match the one used by the website.
+pub const CLASS_PREFIX: &str = "hl-";
+
+/// The pair of syntect themes used to generate the stylesheet for highlighted code.
+#[derive(Deserialize)]
+pub struct SyntaxThemes {
+    pub dark: String,
+    pub light: String,
+    /// The path the stylesheet is linked to as, such as `./assets/css/syntax.css`.
+    pub path: PathBuf,
+    pub output: PathBuf,
+}
+
+impl SyntaxThemes {
+    /// Generates a stylesheet using the dark theme, or the light one if the browser prefers it.
+    pub fn generate_css(&self) -> Result<String> {
+        let themes = ThemeSet::load_defaults();
+        let theme = |name: &str| {
+            themes.themes.get(name).ok_or_else(|| {
+                let available = themes.themes.keys().cloned().collect::<Vec<_>>();
+                eyre!("unknown syntax theme `{name}`, expected one of {available:?}")
+            })
+        };
+
+        Ok(format!(
+            "@media not (prefers-color-scheme: light) {{\n{dark}}}\n\
+             @media (prefers-color-scheme: light) {{\n{light}}}\n",
+            // dark code blocks keep the site's own background
+            dark = Self::theme_css(theme(&self.dark)?, false)?,
+            light = Self::theme_css(theme(&self.light)?, true)?,
+        ))
+    }
+
+    fn theme_css(theme: &Theme, background: bool) -> Result<String> {
+        let mut css = String::from("pre.syntax-highlighting {\n");
+        if let Some(foreground) = theme.settings.foreground {
+            css.push_str(&format!("color: {};\n", hex(foreground)));
+        }
+        if let (true, Some(bg)) = (background, theme.settings.background) {
+            css.push_str(&format!("background-color: {} !important;\n", hex(bg)));
+        }
+        css.push_str("}\n");
+        css.push_str(&css_for_theme_with_class_style(
+            theme,
+            ClassStyle::SpacedPrefixed {
+                prefix: CLASS_PREFIX,
+            },
+        )?);
+        Ok(css)
+    }
+}
+
+fn hex(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
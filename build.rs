@@ -1,6 +1,30 @@
+use std::path::Path;
+
+use syntect::parsing::SyntaxSet;
+
 fn main() {
     println!("cargo::rerun-if-env-changed=ASSET_INDEX");
     if std::env::var("ASSET_INDEX").is_err() {
         println!("cargo::rustc-env=ASSET_INDEX=../assetindex.json")
     }
+
+    build_syntaxes();
+}
+
+/// Combines syntect's default syntaxes with the ones in `syntaxes/`, and dumps them for
+/// `markdown.rs` to load. Invalid syntaxes, including ones with broken regexes, fail the build.
+fn build_syntaxes() {
+    println!("cargo::rerun-if-changed=syntaxes");
+
+    let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+    builder
+        .add_from_folder("syntaxes", true)
+        .expect("failed to load syntaxes");
+
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    syntect::dumps::dump_to_file(
+        &builder.build(),
+        Path::new(&out_dir).join("syntaxes.packdump"),
+    )
+    .expect("failed to dump syntaxes");
 }
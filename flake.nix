@@ -45,7 +45,9 @@
             filter = path: type: (craneLib.filterCargoSources path type)
               || (builtins.match ".*html$" path != null)
               || (builtins.match ".*/assets/images/pfp\\.png$" path != null)
-              || (builtins.match ".*/(blog|projects)/.*\\.md$" path != null);
+              || (builtins.match ".*/(blog|projects)/.*\\.md$" path != null)
+              || (builtins.match ".*/link-schemes/icons/.*\\.svg$" path != null)
+              || (builtins.match ".*/syntaxes/.*\\.sublime-syntax$" path != null);
             name = "source";
           };
         };
@@ -0,0 +1,1071 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs::Permissions,
+    os::unix::fs::PermissionsExt,
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use axum::http::{header::DATE, HeaderMap, Method};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::{
+    header::{ACCEPT, CONTENT_TYPE},
+    Client, Url,
+};
+use rsa::{
+    pkcs1::DecodeRsaPublicKey,
+    pkcs1v15::{Signature, SigningKey, VerifyingKey},
+    pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey, LineEnding},
+    rand_core::OsRng,
+    sha2::{Digest, Sha256},
+    signature::{SignatureEncoding, Signer, Verifier},
+    RsaPrivateKey, RsaPublicKey,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+
+use crate::{
+    apis::public::{is_public_url, PUBLIC_CLIENT},
+    content::ContentIndex,
+    error::{Result, WebsiteError},
+    routes::feeds::{FeedEntry, FeedInfo, FeedPost, FEED_ICON},
+    storage::{load_json, save_json},
+};
+
+pub const ACTIVITY_JSON: &str = "application/activity+json";
+/// The blog's WebFinger address, `@blog@ashhhleyyy.dev`.
+pub const ACCOUNT: &str = "acct:blog@ashhhleyyy.dev";
+pub const ACTOR_ID: &str = "https://ashhhleyyy.dev/activitypub/actor";
+const KEY_ID: &str = "https://ashhhleyyy.dev/activitypub/actor#main-key";
+const INBOX: &str = "https://ashhhleyyy.dev/activitypub/inbox";
+const OUTBOX: &str = "https://ashhhleyyy.dev/activitypub/outbox";
+const FOLLOWERS: &str = "https://ashhhleyyy.dev/activitypub/followers";
+const BLOG_URL: &str = "https://ashhhleyyy.dev/blog/";
+
+const ACTIVITYSTREAMS: &str = "https://www.w3.org/ns/activitystreams";
+const SECURITY: &str = "https://w3id.org/security/v1";
+const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+
+const KEY_BITS: usize = 2048;
+const OUTBOX_PAGE_SIZE: usize = 20;
+/// How often to check whether a post has been published that followers haven't been sent yet.
+const DELIVERY_INTERVAL: Duration = Duration::from_secs(60);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Signed requests dated further than this from now are rejected, to limit replays.
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(12 * 60 * 60);
+/// Forget every cached public key once this many have been seen.
+const MAX_PUBLIC_KEYS: usize = 1024;
+
+/// The ID of an ActivityPub object for a post, which is the post's URL under `/activitypub`.
+fn object_id(url: &str) -> String {
+    format!("https://ashhhleyyy.dev/activitypub{url}")
+}
+
+fn format_date(date: OffsetDateTime) -> String {
+    date.format(&Rfc3339).expect("failed to format date")
+}
+
+/// Whether two URLs are hosted on the same server.
+fn same_origin(a: &str, b: &str) -> bool {
+    Url::parse(a)
+        .ok()
+        .zip(Url::parse(b).ok())
+        .is_some_and(|(a, b)| a.origin() == b.origin())
+}
+
+/// References to other objects can either be their ID, or the object itself.
+fn id_of(value: &Value) -> Option<&str> {
+    value.as_str().or_else(|| value["id"].as_str())
+}
+
+pub fn webfinger() -> Value {
+    json!({
+        "subject": ACCOUNT,
+        "aliases": [ACTOR_ID, BLOG_URL],
+        "links": [
+            {
+                "rel": "self",
+                "type": ACTIVITY_JSON,
+                "href": ACTOR_ID,
+            },
+            {
+                "rel": "http://webfinger.net/rel/profile-page",
+                "type": "text/html",
+                "href": BLOG_URL,
+            },
+        ],
+    })
+}
+
+pub fn article(post: &FeedPost) -> Value {
+    let url = post.post.url();
+    let tags = post
+        .post
+        .tags()
+        .iter()
+        .map(|tag| {
+            json!({
+                "type": "Hashtag",
+                "name": format!("#{tag}"),
+                "href": format!("https://ashhhleyyy.dev/blog/tags/{tag}"),
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut article = json!({
+        "id": object_id(&url),
+        "type": "Article",
+        "attributedTo": ACTOR_ID,
+        "name": post.post.title(),
+        "content": post.html(),
+        "url": format!("https://ashhhleyyy.dev{url}"),
+        "published": format_date(post.post.published()),
+        "to": [PUBLIC],
+        "cc": [FOLLOWERS],
+        "tag": tags,
+    });
+    if post.post.updated() > post.post.published() {
+        article["updated"] = format_date(post.post.updated()).into();
+    }
+    article
+}
+
+fn create(post: &FeedPost) -> Value {
+    json!({
+        "id": format!("{}#create", object_id(&post.post.url())),
+        "type": "Create",
+        "actor": ACTOR_ID,
+        "published": format_date(post.post.published()),
+        "to": [PUBLIC],
+        "cc": [FOLLOWERS],
+        "object": article(post),
+    })
+}
+
+/// Adds the JSON-LD context to a top-level document.
+pub fn with_context(mut document: Value) -> Value {
+    document["@context"] = ACTIVITYSTREAMS.into();
+    document
+}
+
+/// The outbox of published posts, newest first. Without a page this is the collection itself,
+/// which links to its pages.
+pub fn outbox(posts: &[&FeedPost], page: Option<usize>) -> Option<Value> {
+    let page_count = posts.len().div_ceil(OUTBOX_PAGE_SIZE).max(1);
+    let page_url = |page: usize| format!("{OUTBOX}?page={page}");
+    let Some(page) = page else {
+        return Some(with_context(json!({
+            "id": OUTBOX,
+            "type": "OrderedCollection",
+            "totalItems": posts.len(),
+            "first": page_url(1),
+            "last": page_url(page_count),
+        })));
+    };
+    if page == 0 || page > page_count {
+        return None;
+    }
+
+    let items = posts
+        .iter()
+        .skip((page - 1) * OUTBOX_PAGE_SIZE)
+        .take(OUTBOX_PAGE_SIZE)
+        .map(|post| create(post))
+        .collect::<Vec<_>>();
+    let mut collection_page = with_context(json!({
+        "id": page_url(page),
+        "type": "OrderedCollectionPage",
+        "partOf": OUTBOX,
+        "orderedItems": items,
+    }));
+    if page > 1 {
+        collection_page["prev"] = page_url(page - 1).into();
+    }
+    if page < page_count {
+        collection_page["next"] = page_url(page + 1).into();
+    }
+    Some(collection_page)
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Follower {
+    inbox: String,
+    /// Used instead of `inbox` for posts, so each server only gets a post once.
+    shared_inbox: Option<String>,
+    /// The ID of their Follow activity, which an Undo can refer to instead of including it.
+    #[serde(default)]
+    follow: Option<String>,
+}
+
+/// The parts of a `Signature` header we need to verify it.
+struct SignatureHeader {
+    key_id: String,
+    headers: Vec<String>,
+    signature: Vec<u8>,
+}
+
+fn parse_signature(header: &str) -> Option<SignatureHeader> {
+    static PARAM_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r#"(\w+)="([^"]*)""#).unwrap());
+    let params = PARAM_REGEX
+        .captures_iter(header)
+        .map(|captures| {
+            let (_, [name, value]) = captures.extract();
+            (name, value)
+        })
+        .collect::<HashMap<_, _>>();
+    Some(SignatureHeader {
+        key_id: params.get("keyId")?.to_string(),
+        // the date is all that's signed if no headers are given
+        headers: params
+            .get("headers")
+            .unwrap_or(&"date")
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .collect(),
+        signature: BASE64_STANDARD.decode(params.get("signature")?).ok()?,
+    })
+}
+
+/// Builds the string that is signed for a request, from the headers listed in its signature.
+fn signing_string(
+    headers: &[String],
+    method: &Method,
+    path: &str,
+    values: &HeaderMap,
+) -> Option<String> {
+    headers
+        .iter()
+        .map(|name| {
+            let value = if name == "(request-target)" {
+                format!("{} {path}", method.as_str().to_lowercase())
+            } else {
+                let values = values
+                    .get_all(name)
+                    .iter()
+                    .map(|value| value.to_str().ok())
+                    .collect::<Option<Vec<_>>>()?;
+                if values.is_empty() {
+                    return None;
+                }
+                values.join(", ")
+            };
+            Some(format!("{name}: {value}"))
+        })
+        .collect::<Option<Vec<_>>>()
+        .map(|lines| lines.join("\n"))
+}
+
+/// The blog's ActivityPub actor, along with its followers and the posts they've been sent.
+pub struct ActivityPub {
+    key: SigningKey<Sha256>,
+    client: Client,
+    public_key_pem: String,
+    dir: PathBuf,
+    /// By actor ID.
+    followers: RwLock<BTreeMap<String, Follower>>,
+    /// Held while saving followers, so an older copy can't be saved over a newer one.
+    saving_followers: tokio::sync::Mutex<()>,
+    /// URLs of posts that have already been sent to followers.
+    delivered: Mutex<BTreeSet<String>>,
+    /// Public keys of other actors, along with who owns them, by key ID.
+    public_keys: Mutex<HashMap<String, (String, RsaPublicKey)>>,
+}
+
+impl ActivityPub {
+    /// Loads the actor's key and followers from `dir`, creating them if needed, and starts
+    /// sending new posts to followers.
+    pub async fn start(dir: PathBuf, content: Arc<ContentIndex>) -> Result<Arc<Self>> {
+        tokio::fs::create_dir_all(&dir).await?;
+
+        let key_path = dir.join("activitypub.pem");
+        let key = match tokio::fs::read_to_string(&key_path).await {
+            Ok(pem) => {
+                // keys written by older versions could be read by anyone
+                tokio::fs::set_permissions(&key_path, Permissions::from_mode(0o600)).await?;
+                RsaPrivateKey::from_pkcs8_pem(&pem)
+                    .map_err(|_| WebsiteError::ActivityPub("invalid private key"))?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                info!("generating a new key for the activitypub actor");
+                let key = RsaPrivateKey::new(&mut OsRng, KEY_BITS)
+                    .map_err(|_| WebsiteError::ActivityPub("failed to generate key"))?;
+                let pem = key
+                    .to_pkcs8_pem(LineEnding::LF)
+                    .map_err(|_| WebsiteError::ActivityPub("failed to encode key"))?;
+                // only we should be able to read the private key
+                let mut file = OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .mode(0o600)
+                    .open(&key_path)
+                    .await?;
+                file.write_all(pem.as_bytes()).await?;
+                file.sync_all().await?;
+                key
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let public_key_pem = key
+            .to_public_key()
+            .to_public_key_pem(LineEnding::LF)
+            .map_err(|_| WebsiteError::ActivityPub("failed to encode key"))?;
+
+        let followers = load_json(&dir.join("followers.json"))
+            .await?
+            .unwrap_or_default();
+        let delivered = match load_json(&dir.join("delivered.json")).await? {
+            Some(delivered) => delivered,
+            None => {
+                // don't flood new followers with everything posted before the blog could be followed
+                let delivered = content
+                    .published_feed_posts()
+                    .iter()
+                    .map(|post| post.post.url())
+                    .collect::<BTreeSet<_>>();
+                save_json(&dir.join("delivered.json"), &delivered).await?;
+                delivered
+            }
+        };
+
+        let activitypub = Arc::new(Self {
+            key: SigningKey::new(key),
+            client: PUBLIC_CLIENT.clone(),
+            public_key_pem,
+            dir,
+            followers: RwLock::new(followers),
+            saving_followers: Default::default(),
+            delivered: Mutex::new(delivered),
+            public_keys: Mutex::new(HashMap::new()),
+        });
+
+        let worker = activitypub.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(DELIVERY_INTERVAL);
+            loop {
+                interval.tick().await;
+                worker.deliver_new_posts(&content).await;
+            }
+        });
+
+        Ok(activitypub)
+    }
+
+    pub fn actor(&self) -> Value {
+        let info = FeedInfo::blog();
+        json!({
+            "@context": [ACTIVITYSTREAMS, SECURITY],
+            "id": ACTOR_ID,
+            "type": "Service",
+            "preferredUsername": "blog",
+            "name": info.title,
+            "summary": maud::html! { p { (info.description) } }.0,
+            "url": BLOG_URL,
+            "icon": {
+                "type": "Image",
+                "url": FEED_ICON,
+            },
+            "inbox": INBOX,
+            "outbox": OUTBOX,
+            "followers": FOLLOWERS,
+            "manuallyApprovesFollowers": false,
+            "discoverable": true,
+            "publicKey": {
+                "id": KEY_ID,
+                "owner": ACTOR_ID,
+                "publicKeyPem": self.public_key_pem,
+            },
+        })
+    }
+
+    /// Only the number of followers is public, not who they are.
+    pub fn followers(&self) -> Value {
+        with_context(json!({
+            "id": FOLLOWERS,
+            "type": "OrderedCollection",
+            "totalItems": self.followers.read().unwrap().len(),
+        }))
+    }
+
+    /// Handles an activity posted to the inbox, once its signature has been checked.
+    pub async fn receive(
+        self: &Arc<Self>,
+        method: &Method,
+        path: &str,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<()> {
+        let owner = self.verify_request(method, path, headers, body).await?;
+        let activity = serde_json::from_slice::<Value>(body)?;
+        if activity["actor"].as_str() != Some(owner.as_str()) {
+            return Err(WebsiteError::Signature(
+                "activity wasn't signed by its actor",
+            ));
+        }
+
+        match activity["type"].as_str() {
+            Some("Follow") if id_of(&activity["object"]) == Some(ACTOR_ID) => {
+                self.follow(owner, activity).await
+            }
+            Some("Undo") if self.undoes_follow(&owner, &activity["object"]) => {
+                if self.followers.write().unwrap().remove(&owner).is_some() {
+                    info!(actor = owner, "lost a follower");
+                    self.save_followers().await?;
+                }
+                Ok(())
+            }
+            kind => {
+                debug!(?kind, actor = owner, "ignoring activity");
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether the object of an Undo from `actor` is their follow, either by ID or included in full.
+    fn undoes_follow(&self, actor: &str, object: &Value) -> bool {
+        match object.as_str() {
+            Some(id) => self
+                .followers
+                .read()
+                .unwrap()
+                .get(actor)
+                .is_some_and(|follower| follower.follow.as_deref() == Some(id)),
+            None => object["type"] == "Follow" && id_of(&object["actor"]) == Some(actor),
+        }
+    }
+
+    async fn follow(self: &Arc<Self>, actor: String, follow: Value) -> Result<()> {
+        let document = self.fetch(&actor).await?;
+        // posts are sent to these, so they must belong to the follower
+        let is_own = |inbox: &str| {
+            same_origin(inbox, &actor) && Url::parse(inbox).is_ok_and(|url| is_public_url(&url))
+        };
+        let inbox = document["inbox"]
+            .as_str()
+            .ok_or(WebsiteError::ActivityPub("follower has no inbox"))?
+            .to_owned();
+        if !is_own(&inbox) {
+            return Err(WebsiteError::ActivityPub(
+                "follower's inbox isn't on their server",
+            ));
+        }
+        let shared_inbox = document["endpoints"]["sharedInbox"]
+            .as_str()
+            .filter(|shared_inbox| is_own(shared_inbox))
+            .map(str::to_owned);
+        self.followers.write().unwrap().insert(
+            actor.clone(),
+            Follower {
+                inbox: inbox.clone(),
+                shared_inbox,
+                follow: follow["id"].as_str().map(str::to_owned),
+            },
+        );
+        self.save_followers().await?;
+        info!(actor, "gained a follower");
+
+        let accept = with_context(json!({
+            "id": format!("{ACTOR_ID}#accepts/{:016x}", fastrand::u64(..)),
+            "type": "Accept",
+            "actor": ACTOR_ID,
+            "object": follow,
+        }));
+        let activitypub = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = activitypub.deliver(&inbox, &accept).await {
+                warn!(inbox, ?e, "failed to accept follow");
+            }
+        });
+        Ok(())
+    }
+
+    async fn save_followers(&self) -> Result<()> {
+        // the copy is taken once it's our turn, so the last save has every change
+        let _saving = self.saving_followers.lock().await;
+        let followers = self.followers.read().unwrap().clone();
+        save_json(&self.dir.join("followers.json"), &followers).await
+    }
+
+    /// Checks a request's HTTP signature, returning the actor who signed it.
+    async fn verify_request(
+        &self,
+        method: &Method,
+        path: &str,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<String> {
+        let signature = headers
+            .get("signature")
+            .and_then(|header| header.to_str().ok())
+            .and_then(parse_signature)
+            .ok_or(WebsiteError::Signature("missing or malformed signature"))?;
+        for required in ["(request-target)", "host", "date", "digest"] {
+            if !signature.headers.iter().any(|name| name == required) {
+                return Err(WebsiteError::Signature(
+                    "signature must cover the request target, host, date and digest",
+                ));
+            }
+        }
+
+        let date = headers
+            .get(DATE)
+            .and_then(|date| date.to_str().ok())
+            .and_then(|date| httpdate::parse_http_date(date).ok())
+            .ok_or(WebsiteError::Signature("missing or malformed date"))?;
+        let now = SystemTime::now();
+        let skew = now
+            .duration_since(date)
+            .or_else(|_| date.duration_since(now))
+            .unwrap_or_default();
+        if skew > MAX_CLOCK_SKEW {
+            return Err(WebsiteError::Signature("request is too old"));
+        }
+
+        let expected_digest = BASE64_STANDARD.encode(Sha256::digest(body));
+        let digest_matches = headers
+            .get_all("digest")
+            .iter()
+            .filter_map(|header| header.to_str().ok())
+            .flat_map(|header| header.split(','))
+            .filter_map(|digest| digest.trim().split_once('='))
+            .any(|(algorithm, digest)| {
+                algorithm.eq_ignore_ascii_case("sha-256") && digest == expected_digest
+            });
+        if !digest_matches {
+            return Err(WebsiteError::Signature("digest doesn't match body"));
+        }
+
+        let signing_string = signing_string(&signature.headers, method, path, headers)
+            .ok_or(WebsiteError::Signature("signed headers are missing"))?;
+        let signature_bytes = Signature::try_from(signature.signature.as_slice())
+            .map_err(|_| WebsiteError::Signature("malformed signature"))?;
+        let verify = |key: RsaPublicKey| {
+            VerifyingKey::<Sha256>::new(key)
+                .verify(signing_string.as_bytes(), &signature_bytes)
+                .is_ok()
+        };
+
+        let (owner, key) = self.public_key(&signature.key_id, false).await?;
+        if verify(key) {
+            return Ok(owner);
+        }
+        // the key may have been rotated since it was cached
+        let (owner, key) = self.public_key(&signature.key_id, true).await?;
+        if verify(key) {
+            Ok(owner)
+        } else {
+            Err(WebsiteError::Signature("signature doesn't match"))
+        }
+    }
+
+    /// Looks up a public key and its owner, fetching it unless it's cached.
+    async fn public_key(&self, key_id: &str, refresh: bool) -> Result<(String, RsaPublicKey)> {
+        if !refresh {
+            if let Some(key) = self.public_keys.lock().unwrap().get(key_id) {
+                return Ok(key.clone());
+            }
+        }
+
+        let document = self.fetch(key_id).await?;
+        // the key is usually embedded in its owner's actor document
+        let key = match &document["publicKey"] {
+            Value::Object(_) => &document["publicKey"],
+            _ => &document,
+        };
+        if key["id"].as_str() != Some(key_id) {
+            return Err(WebsiteError::Signature("fetched key doesn't match key ID"));
+        }
+        let owner = key["owner"]
+            .as_str()
+            .ok_or(WebsiteError::Signature("key has no owner"))?;
+        // otherwise anyone could publish a key claiming to belong to someone else
+        if !same_origin(owner, key_id) {
+            return Err(WebsiteError::Signature("key isn't hosted by its owner"));
+        }
+        let pem = key["publicKeyPem"]
+            .as_str()
+            .ok_or(WebsiteError::Signature("key has no PEM"))?;
+        let public_key = RsaPublicKey::from_public_key_pem(pem)
+            .or_else(|_| RsaPublicKey::from_pkcs1_pem(pem))
+            .map_err(|_| WebsiteError::Signature("unsupported public key"))?;
+
+        let mut public_keys = self.public_keys.lock().unwrap();
+        if public_keys.len() >= MAX_PUBLIC_KEYS {
+            public_keys.clear();
+        }
+        let key = (owner.to_owned(), public_key);
+        public_keys.insert(key_id.to_owned(), key.clone());
+        Ok(key)
+    }
+
+    /// Signs a request, covering its target, host, date and, if it has a body, its digest.
+    fn sign(&self, method: &Method, url: &Url, date: &str, digest: Option<&str>) -> String {
+        let host = match url.port() {
+            Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+            None => url.host_str().unwrap_or_default().to_owned(),
+        };
+        let path = match url.query() {
+            Some(query) => format!("{}?{query}", url.path()),
+            None => url.path().to_owned(),
+        };
+        let mut headers = vec![
+            format!(
+                "(request-target): {} {path}",
+                method.as_str().to_lowercase()
+            ),
+            format!("host: {host}"),
+            format!("date: {date}"),
+        ];
+        let mut names = "(request-target) host date".to_owned();
+        if let Some(digest) = digest {
+            headers.push(format!("digest: {digest}"));
+            names.push_str(" digest");
+        }
+        let signature = self.key.sign(headers.join("\n").as_bytes());
+        format!(
+            r#"keyId="{KEY_ID}",algorithm="rsa-sha256",headers="{names}",signature="{}""#,
+            BASE64_STANDARD.encode(signature.to_bytes())
+        )
+    }
+
+    /// Fetches an ActivityPub document, signing the request for servers that require it.
+    async fn fetch(&self, url: &str) -> Result<Value> {
+        let mut url =
+            Url::parse(url).map_err(|_| WebsiteError::ActivityPub("invalid object URL"))?;
+        url.set_fragment(None);
+        if !is_public_url(&url) {
+            return Err(WebsiteError::PrivateUrl);
+        }
+        let date = httpdate::fmt_http_date(SystemTime::now());
+        let signature = self.sign(&Method::GET, &url, &date, None);
+        let document = self
+            .client
+            .get(url)
+            .header(ACCEPT, ACTIVITY_JSON)
+            .header(DATE, date)
+            .header("signature", signature)
+            .timeout(FETCH_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(document)
+    }
+
+    async fn deliver(&self, inbox: &str, activity: &Value) -> Result<()> {
+        let url = Url::parse(inbox).map_err(|_| WebsiteError::ActivityPub("invalid inbox URL"))?;
+        if !is_public_url(&url) {
+            return Err(WebsiteError::PrivateUrl);
+        }
+        let body = serde_json::to_vec(activity)?;
+        let date = httpdate::fmt_http_date(SystemTime::now());
+        let digest = format!("SHA-256={}", BASE64_STANDARD.encode(Sha256::digest(&body)));
+        let signature = self.sign(&Method::POST, &url, &date, Some(&digest));
+        self.client
+            .post(url)
+            .header(CONTENT_TYPE, ACTIVITY_JSON)
+            .header(DATE, date)
+            .header("digest", digest)
+            .header("signature", signature)
+            .timeout(FETCH_TIMEOUT)
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Sends followers any posts that have been published since they were last checked for.
+    async fn deliver_new_posts(&self, content: &ContentIndex) {
+        let posts = content.published_feed_posts();
+        // oldest first, so they show up in order
+        for post in posts.iter().rev() {
+            let url = post.post.url();
+            if self.delivered.lock().unwrap().contains(&url) {
+                continue;
+            }
+
+            let inboxes = self
+                .followers
+                .read()
+                .unwrap()
+                .values()
+                .map(|follower| {
+                    follower
+                        .shared_inbox
+                        .clone()
+                        .unwrap_or_else(|| follower.inbox.clone())
+                })
+                .collect::<BTreeSet<_>>();
+            let activity = with_context(create(post));
+            for inbox in &inboxes {
+                if let Err(e) = self.deliver(inbox, &activity).await {
+                    warn!(inbox, ?e, "failed to deliver post");
+                }
+            }
+            info!(url, inboxes = inboxes.len(), "delivered post to followers");
+
+            let delivered = {
+                let mut delivered = self.delivered.lock().unwrap();
+                delivered.insert(url);
+                delivered.clone()
+            };
+            if let Err(e) = save_json(&self.dir.join("delivered.json"), &delivered).await {
+                error!(?e, "failed to save delivered posts");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::{
+        extract::State,
+        http::{header::HOST, HeaderValue},
+        routing::get,
+        Json, Router,
+    };
+
+    use super::*;
+
+    const PATH: &str = "/activitypub/inbox";
+
+    /// Generating keys is slow, so the tests share one.
+    static KEY: Lazy<RsaPrivateKey> =
+        Lazy::new(|| RsaPrivateKey::new(&mut OsRng, 1024).expect("failed to generate key"));
+
+    fn public_key_pem() -> String {
+        KEY.to_public_key()
+            .to_public_key_pem(LineEnding::LF)
+            .unwrap()
+    }
+
+    /// Serves a remote actor on localhost, counting how often its key is fetched.
+    async fn remote(fetches: Arc<AtomicUsize>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let origin = format!("http://localhost:{}", listener.local_addr().unwrap().port());
+        let actor = |inbox: String| {
+            let origin = origin.clone();
+            move |State(fetches): State<Arc<AtomicUsize>>| async move {
+                fetches.fetch_add(1, Ordering::SeqCst);
+                Json(json!({
+                    "id": format!("{origin}/actor"),
+                    "inbox": inbox,
+                    "publicKey": {
+                        "id": format!("{origin}/actor#main-key"),
+                        "owner": format!("{origin}/actor"),
+                        "publicKeyPem": public_key_pem(),
+                    },
+                }))
+            }
+        };
+        let impostor = {
+            let origin = origin.clone();
+            move || async move {
+                Json(json!({
+                    "id": format!("{origin}/impostor#main-key"),
+                    "owner": "https://example.com/actor",
+                    "publicKeyPem": public_key_pem(),
+                }))
+            }
+        };
+        let app = Router::new()
+            .route("/actor", get(actor(format!("{origin}/inbox"))))
+            .route("/elsewhere", get(actor("https://example.com/inbox".into())))
+            .route("/impostor", get(impostor))
+            .with_state(fetches);
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        origin
+    }
+
+    fn activitypub(name: &str) -> Arc<ActivityPub> {
+        let dir = std::env::temp_dir().join(format!("activitypub-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        Arc::new(ActivityPub {
+            key: SigningKey::new(KEY.clone()),
+            client: Client::new(),
+            public_key_pem: public_key_pem(),
+            dir,
+            followers: Default::default(),
+            saving_followers: Default::default(),
+            delivered: Default::default(),
+            public_keys: Default::default(),
+        })
+    }
+
+    /// Signs a POST to the inbox the way other servers do.
+    fn signed_headers(key_id: &str, body: &[u8], date: SystemTime) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(HOST, HeaderValue::from_static("ashhhleyyy.dev"));
+        headers.insert(DATE, httpdate::fmt_http_date(date).parse().unwrap());
+        let digest = format!("SHA-256={}", BASE64_STANDARD.encode(Sha256::digest(body)));
+        headers.insert("digest", digest.parse().unwrap());
+
+        let names = ["(request-target)", "host", "date", "digest"].map(str::to_owned);
+        let signing_string = signing_string(&names, &Method::POST, PATH, &headers).unwrap();
+        let signature = SigningKey::<Sha256>::new(KEY.clone()).sign(signing_string.as_bytes());
+        let header = format!(
+            r#"keyId="{key_id}",algorithm="rsa-sha256",headers="{}",signature="{}""#,
+            names.join(" "),
+            BASE64_STANDARD.encode(signature.to_bytes())
+        );
+        headers.insert("signature", header.parse().unwrap());
+        headers
+    }
+
+    async fn verify(activitypub: &ActivityPub, headers: &HeaderMap, body: &[u8]) -> Result<String> {
+        activitypub
+            .verify_request(&Method::POST, PATH, headers, body)
+            .await
+    }
+
+    #[tokio::test]
+    async fn valid_signature_is_accepted_and_key_is_cached() {
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let origin = remote(fetches.clone()).await;
+        let activitypub = activitypub("valid");
+        let body = br#"{"type":"Like"}"#;
+        let headers = signed_headers(&format!("{origin}/actor#main-key"), body, SystemTime::now());
+
+        let owner = verify(&activitypub, &headers, body).await.unwrap();
+        assert_eq!(owner, format!("{origin}/actor"));
+        verify(&activitypub, &headers, body).await.unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn digest_must_match_body() {
+        let origin = remote(Default::default()).await;
+        let activitypub = activitypub("digest");
+        let headers = signed_headers(
+            &format!("{origin}/actor#main-key"),
+            br#"{"type":"Like"}"#,
+            SystemTime::now(),
+        );
+        let result = verify(&activitypub, &headers, br#"{"type":"Delete"}"#).await;
+        assert!(matches!(
+            result,
+            Err(WebsiteError::Signature("digest doesn't match body"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn date_must_be_recent() {
+        let origin = remote(Default::default()).await;
+        let activitypub = activitypub("date");
+        let body = br#"{"type":"Like"}"#;
+        let key_id = format!("{origin}/actor#main-key");
+        let skew = MAX_CLOCK_SKEW + Duration::from_secs(60);
+        for date in [SystemTime::now() - skew, SystemTime::now() + skew] {
+            let headers = signed_headers(&key_id, body, date);
+            let result = verify(&activitypub, &headers, body).await;
+            assert!(matches!(
+                result,
+                Err(WebsiteError::Signature("request is too old"))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn signature_must_cover_required_headers() {
+        let origin = remote(Default::default()).await;
+        let activitypub = activitypub("headers");
+        let body = br#"{"type":"Like"}"#;
+        let mut headers =
+            signed_headers(&format!("{origin}/actor#main-key"), body, SystemTime::now());
+        let signature = headers["signature"]
+            .to_str()
+            .unwrap()
+            .replace(" digest", "");
+        headers.insert("signature", signature.parse().unwrap());
+        assert!(verify(&activitypub, &headers, body).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn key_must_be_hosted_by_its_owner() {
+        let origin = remote(Default::default()).await;
+        let activitypub = activitypub("impostor");
+        let body = br#"{"type":"Like"}"#;
+        let headers = signed_headers(
+            &format!("{origin}/impostor#main-key"),
+            body,
+            SystemTime::now(),
+        );
+        let result = verify(&activitypub, &headers, body).await;
+        assert!(matches!(
+            result,
+            Err(WebsiteError::Signature("key isn't hosted by its owner"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn unknown_key_is_rejected() {
+        let origin = remote(Default::default()).await;
+        let activitypub = activitypub("unknown");
+        let body = br#"{"type":"Like"}"#;
+        let headers = signed_headers(
+            &format!("{origin}/missing#main-key"),
+            body,
+            SystemTime::now(),
+        );
+        assert!(verify(&activitypub, &headers, body).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn follower_inbox_must_be_on_their_server() {
+        let origin = remote(Default::default()).await;
+        let activitypub = activitypub("follow");
+
+        let actor = format!("{origin}/elsewhere");
+        assert!(activitypub.follow(actor, json!({})).await.is_err());
+        assert!(activitypub.followers.read().unwrap().is_empty());
+
+        let actor = format!("{origin}/actor");
+        let follow = json!({ "id": format!("{origin}/follows/1") });
+        activitypub.follow(actor.clone(), follow).await.unwrap();
+        let followers = activitypub.followers.read().unwrap();
+        assert_eq!(followers[&actor].inbox, format!("{origin}/inbox"));
+        assert_eq!(
+            followers[&actor].follow,
+            Some(format!("{origin}/follows/1"))
+        );
+    }
+
+    #[tokio::test]
+    async fn only_the_follower_can_undo_their_follow() {
+        let origin = remote(Default::default()).await;
+        let activitypub = activitypub("undo");
+        let actor = format!("{origin}/actor");
+        activitypub.followers.write().unwrap().insert(
+            "https://example.com/actor".into(),
+            Follower {
+                inbox: "https://example.com/inbox".into(),
+                shared_inbox: None,
+                follow: Some("https://example.com/follows/1".into()),
+            },
+        );
+
+        let undo = serde_json::to_vec(&json!({
+            "type": "Undo",
+            "actor": actor,
+            "object": {
+                "type": "Follow",
+                "actor": "https://example.com/actor",
+                "object": ACTOR_ID,
+            },
+        }))
+        .unwrap();
+        let headers = signed_headers(&format!("{actor}#main-key"), &undo, SystemTime::now());
+        activitypub
+            .receive(&Method::POST, PATH, &headers, &undo)
+            .await
+            .unwrap();
+        assert_eq!(activitypub.followers.read().unwrap().len(), 1);
+    }
+
+    /// Sends an Undo of `object` from the actor served by `remote`.
+    async fn undo(activitypub: &Arc<ActivityPub>, actor: &str, object: Value) -> Result<()> {
+        let undo = serde_json::to_vec(&json!({
+            "type": "Undo",
+            "actor": actor,
+            "object": object,
+        }))
+        .unwrap();
+        let headers = signed_headers(&format!("{actor}#main-key"), &undo, SystemTime::now());
+        activitypub
+            .receive(&Method::POST, PATH, &headers, &undo)
+            .await
+    }
+
+    fn add_follower(activitypub: &ActivityPub, actor: &str, follow: &str) {
+        activitypub.followers.write().unwrap().insert(
+            actor.to_owned(),
+            Follower {
+                inbox: format!("{actor}/inbox"),
+                shared_inbox: None,
+                follow: Some(follow.to_owned()),
+            },
+        );
+    }
+
+    #[tokio::test]
+    async fn follow_can_be_undone_by_id() {
+        let origin = remote(Default::default()).await;
+        let activitypub = activitypub("undo-id");
+        let actor = format!("{origin}/actor");
+        add_follower(&activitypub, &actor, &format!("{origin}/follows/1"));
+
+        // some other activity of theirs
+        undo(&activitypub, &actor, json!(format!("{origin}/likes/1")))
+            .await
+            .unwrap();
+        assert_eq!(activitypub.followers.read().unwrap().len(), 1);
+
+        undo(&activitypub, &actor, json!(format!("{origin}/follows/1")))
+            .await
+            .unwrap();
+        assert!(activitypub.followers.read().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn follow_can_be_undone_in_full() {
+        let origin = remote(Default::default()).await;
+        let activitypub = activitypub("undo-full");
+        let actor = format!("{origin}/actor");
+        add_follower(&activitypub, &actor, &format!("{origin}/follows/1"));
+
+        let follow = json!({
+            "id": format!("{origin}/follows/1"),
+            "type": "Follow",
+            "actor": actor,
+            "object": ACTOR_ID,
+        });
+        undo(&activitypub, &actor, follow).await.unwrap();
+        assert!(activitypub.followers.read().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn someone_elses_follow_cant_be_undone_by_id() {
+        let origin = remote(Default::default()).await;
+        let activitypub = activitypub("undo-other-id");
+        add_follower(
+            &activitypub,
+            "https://example.com/actor",
+            "https://example.com/follows/1",
+        );
+        let actor = format!("{origin}/actor");
+        undo(&activitypub, &actor, json!("https://example.com/follows/1"))
+            .await
+            .unwrap();
+        assert_eq!(activitypub.followers.read().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn concurrent_saves_keep_every_follower() {
+        let activitypub = activitypub("concurrent");
+        let saves = (0..32).map(|i| {
+            let activitypub = activitypub.clone();
+            tokio::spawn(async move {
+                let actor = format!("https://example.com/users/{i}");
+                add_follower(&activitypub, &actor, &format!("{actor}/follow"));
+                activitypub.save_followers().await.unwrap();
+            })
+        });
+        for save in saves.collect::<Vec<_>>() {
+            save.await.unwrap();
+        }
+
+        let saved =
+            load_json::<BTreeMap<String, Follower>>(&activitypub.dir.join("followers.json"))
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(saved.len(), 32);
+    }
+}
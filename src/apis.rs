@@ -18,6 +18,7 @@ pub const NOWPLAYING_URL: &str = "https://api.ashhhleyyy.dev/playing";
 const MIN_REFRESH_TIME: Duration = Duration::from_secs(5);
 
 pub(crate) mod fedi;
+pub(crate) mod public;
 // TODO: resurrect or yeet
 // pub(crate) mod mediawiki;
 
@@ -48,11 +49,12 @@ impl<T: DeserializeOwned + Sized + Clone> CachingFetcher<T> {
 
     pub async fn get(&self) -> T {
         let now = Instant::now();
-        let last_updated = self.last_updated.lock().await;
+        let mut last_updated = self.last_updated.lock().await;
         if now - *last_updated > MIN_REFRESH_TIME {
             match Self::fetch(&self.url).await {
                 Ok(profile) => {
                     *self.last_state.write().await = profile;
+                    *last_updated = now;
                 }
                 Err(e) => {
                     error!("failed to refresh data: {}", e);
@@ -1,44 +1,139 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
+use ammonia::Builder;
+use maud::Markup;
 use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
 use time::OffsetDateTime;
-use tokio::sync::Mutex;
 
 use crate::error::Result;
 
-use super::CachingFetcher;
+use super::CLIENT;
 
-pub(crate) static POST_FETCHER: Lazy<CachingPostFetcher> = Lazy::new(CachingPostFetcher::new);
+/// Replies nested deeper than this are shown at this depth, so long threads stay readable.
+const MAX_REPLY_DEPTH: usize = 4;
+/// How long a status is cached before it is fetched again.
+const REFRESH_TIME: Duration = Duration::from_secs(60);
+/// How long to wait before trying again after a status couldn't be fetched.
+const ERROR_TTL: Duration = Duration::from_secs(5 * 60);
 
-pub struct CachingPostFetcher {
-    #[allow(clippy::type_complexity)]
-    fetchers: Arc<Mutex<HashMap<(String, String), CachingFetcher<PostData>>>>,
+pub(crate) static POST_FETCHER: Lazy<CachingPostFetcher<PostData>> =
+    Lazy::new(|| CachingPostFetcher::new(""));
+pub(crate) static CONTEXT_FETCHER: Lazy<CachingPostFetcher<StatusContext>> =
+    Lazy::new(|| CachingPostFetcher::new("/context"));
+
+/// The HTML that statuses may contain, which is what Mastodon itself allows through.
+static SANITIZER: Lazy<Builder<'static>> = Lazy::new(|| {
+    let mut builder = Builder::default();
+    builder
+        .add_allowed_classes("a", ["mention", "hashtag", "u-url"])
+        .add_allowed_classes("span", ["h-card", "invisible", "ellipsis"])
+        .link_rel(Some("nofollow noopener noreferrer"));
+    builder
+});
+
+struct CachedStatus<T> {
+    /// The last copy that was fetched successfully, if any.
+    value: Option<T>,
+    /// When the status was last fetched, or tried to be.
+    fetched: Option<Instant>,
+    failed: bool,
+    /// Set while a fetch is in progress, so only one happens at a time.
+    fetching: bool,
 }
 
-impl CachingPostFetcher {
-    pub fn new() -> Self {
+/// Caches something about each status, such as the status itself or its replies.
+///
+/// The lock is never held while fetching, so a slow server can't hold up other requests.
+pub struct CachingPostFetcher<T> {
+    cache: Mutex<HashMap<(String, String), CachedStatus<T>>>,
+    /// Appended to the status's API URL.
+    endpoint: &'static str,
+}
+
+impl<T: DeserializeOwned + Clone + Send + 'static> CachingPostFetcher<T> {
+    pub fn new(endpoint: &'static str) -> Self {
         Self {
-            fetchers: Arc::new(Mutex::new(HashMap::new())),
+            cache: Mutex::new(HashMap::new()),
+            endpoint,
+        }
+    }
+
+    /// Returns the cached copy of a status without waiting, fetching it in the background if
+    /// there isn't one or it's out of date.
+    pub fn cached(&'static self, server: &str, id: &str) -> Option<T> {
+        let (value, fetch) = self.lookup(server, id);
+        if fetch {
+            tokio::spawn(self.refresh(server.to_owned(), id.to_owned()));
         }
+        value
     }
 
-    pub async fn get_post(&self, server: String, id: String) -> Result<PostData> {
-        let mut fetchers = self.fetchers.lock().await;
+    /// Returns a status, waiting for it to be fetched if the cached copy is out of date.
+    pub async fn get_post(&self, server: &str, id: &str) -> Option<T> {
+        match self.lookup(server, id) {
+            (_, true) => self.refresh(server.to_owned(), id.to_owned()).await,
+            (value, false) => value,
+        }
+    }
 
-        let fetcher = if let Some(fetcher) = fetchers.get_mut(&(server.clone(), id.clone())) {
-            fetcher
+    /// Returns the cached copy of a status, and whether the caller should fetch it again.
+    fn lookup(&self, server: &str, id: &str) -> (Option<T>, bool) {
+        let mut cache = self.cache.lock().unwrap();
+        let status = cache
+            .entry((server.to_owned(), id.to_owned()))
+            .or_insert_with(|| CachedStatus {
+                value: None,
+                fetched: None,
+                failed: false,
+                fetching: false,
+            });
+        let ttl = if status.failed {
+            ERROR_TTL
         } else {
-            let fetcher =
-                CachingFetcher::<PostData>::new(format!("https://{server}/api/v1/statuses/{id}"))
-                    .await?;
-            fetchers.insert((server.clone(), id.clone()), fetcher);
-            fetchers.get_mut(&(server, id)).unwrap()
+            REFRESH_TIME
         };
+        let fetch =
+            !status.fetching && status.fetched.is_none_or(|fetched| fetched.elapsed() > ttl);
+        status.fetching |= fetch;
+        (status.value.clone(), fetch)
+    }
 
-        Ok(fetcher.get().await)
+    async fn refresh(&self, server: String, id: String) -> Option<T> {
+        let url = format!("https://{server}/api/v1/statuses/{id}{}", self.endpoint);
+        let result = fetch::<T>(&url).await;
+        if let Err(e) = &result {
+            tracing::warn!(server, id, ?e, "failed to fetch post");
+        }
+
+        let mut cache = self.cache.lock().unwrap();
+        let status = cache.get_mut(&(server, id)).expect("status was cached");
+        status.fetched = Some(Instant::now());
+        status.fetching = false;
+        status.failed = result.is_err();
+        // a server being down for a moment shouldn't make the replies disappear
+        if let Ok(value) = result {
+            status.value = Some(value);
+        }
+        status.value.clone()
     }
 }
 
+async fn fetch<T: DeserializeOwned>(url: &str) -> Result<T> {
+    Ok(CLIENT
+        .get(url)
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
 #[derive(Clone, serde::Deserialize)]
 pub struct PostData {
     pub content: String,
@@ -64,11 +159,75 @@ pub enum Timestamps {
     },
 }
 
+/// The replies to a status.
+#[derive(Clone, serde::Deserialize)]
+pub struct StatusContext {
+    /// Every reply in the thread, not just direct replies.
+    pub descendants: Vec<Reply>,
+}
+
+#[derive(Clone, serde::Deserialize)]
+pub struct Reply {
+    pub id: String,
+    pub in_reply_to_id: Option<String>,
+    #[serde(flatten)]
+    pub post: PostData,
+}
+
+impl StatusContext {
+    /// Renders the replies to the status `id` as nested lists.
+    pub fn replies_html(&self, id: &str) -> Markup {
+        let mut children = HashMap::<&str, Vec<&Reply>>::new();
+        for reply in &self.descendants {
+            if let Some(parent) = &reply.in_reply_to_id {
+                children.entry(parent).or_default().push(reply);
+            }
+        }
+        render_replies(&children, id, 1)
+    }
+}
+
+fn render_replies(children: &HashMap<&str, Vec<&Reply>>, id: &str, depth: usize) -> Markup {
+    let Some(replies) = children.get(id) else {
+        return maud::html! {};
+    };
+    maud::html! {
+        ol.fedi-replies {
+            @for reply in replies {
+                li {
+                    (reply.post.as_html())
+                    @if depth < MAX_REPLY_DEPTH {
+                        (render_replies(children, &reply.id, depth + 1))
+                    }
+                }
+                @if depth >= MAX_REPLY_DEPTH {
+                    @for nested in flatten_replies(children, &reply.id) {
+                        li { (nested.post.as_html()) }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Every reply below `id`, in thread order.
+fn flatten_replies<'a>(children: &HashMap<&str, Vec<&'a Reply>>, id: &str) -> Vec<&'a Reply> {
+    let mut flattened = vec![];
+    let mut stack = children.get(id).cloned().unwrap_or_default();
+    stack.reverse();
+    while let Some(reply) = stack.pop() {
+        flattened.push(reply);
+        if let Some(replies) = children.get(reply.id.as_str()) {
+            stack.extend(replies.iter().rev());
+        }
+    }
+    flattened
+}
+
 #[derive(Clone, serde::Deserialize)]
 pub struct AccountData {
+    /// Used rather than `avatar`, which may be animated.
     pub avatar_static: String,
-    #[allow(unused)] // TODO: why avatar_static not this
-    pub avatar: String,
     pub display_name: String,
     pub fqn: String,
     pub url: String,
@@ -76,12 +235,21 @@ pub struct AccountData {
 
 #[derive(Clone, serde::Deserialize)]
 pub struct Attatchment {
-    pub description: String,
+    /// Alt text, which Mastodon sends as `null` if there isn't any.
+    pub description: Option<String>,
     #[serde(rename = "type")]
     pub ty: String,
     pub url: String,
 }
 
+/// Links in statuses come from other servers, so only web URLs are used, as ammonia does for
+/// their content.
+fn web_url(url: &str) -> Option<&str> {
+    reqwest::Url::parse(url)
+        .is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+        .then_some(url)
+}
+
 fn format_odt(date: OffsetDateTime) -> String {
     let (hour, min) = (date.hour(), date.minute());
     let (year, month, date) = (date.year(), date.month() as u8, date.day());
@@ -89,42 +257,223 @@ fn format_odt(date: OffsetDateTime) -> String {
 }
 
 impl PostData {
+    fn posted(&self) -> String {
+        match self.timestamps {
+            Timestamps::Created { created_at } => format!("Posted {}", format_odt(created_at)),
+            Timestamps::Edited {
+                created_at,
+                edited_at,
+            } => format!(
+                "Posted {} (Edited at {})",
+                format_odt(created_at),
+                format_odt(edited_at)
+            ),
+        }
+    }
+
     pub fn as_html(&self) -> maud::Markup {
         maud::html! {
             .fedi-post {
                 blockquote {
                     .fedi-author {
-                        img.fedi-avatar width="48" height="48" src=(self.account.avatar_static);
+                        @if let Some(avatar) = web_url(&self.account.avatar_static) {
+                            img.fedi-avatar width="48" height="48" src=(avatar);
+                        }
 
-                        a href=(self.account.url) {
-                            (self.account.display_name) " (@" (self.account.fqn) ")"
+                        @if let Some(url) = web_url(&self.account.url) {
+                            a href=(url) {
+                                (self.account.display_name) " (@" (self.account.fqn) ")"
+                            }
+                        } @else {
+                            span {
+                                (self.account.display_name) " (@" (self.account.fqn) ")"
+                            }
                         }
                     }
 
-                    (maud::PreEscaped(self.content.clone()))
+                    (maud::PreEscaped(SANITIZER.clean(&self.content).to_string()))
 
                     @if !self.media_attachments.is_empty() {
                         br;
                     }
 
                     @for attachment in &self.media_attachments {
-                        @if attachment.ty == "image" {
-                            img src=(attachment.url) alt=(attachment.description);
+                        @if let Some(url) = web_url(&attachment.url).filter(|_| attachment.ty == "image") {
+                            img src=(url) alt=[&attachment.description];
                         }
                     }
 
-                    a href=(self.url) {
-                        @match self.timestamps {
-                            Timestamps::Created { created_at } => {
-                                "Posted " (format_odt(created_at))
-                            },
-                            Timestamps::Edited { created_at, edited_at } => {
-                                "Posted " (format_odt(created_at)) " (Edited at " (format_odt(edited_at)) ")"
-                            },
-                        }
+                    @if let Some(url) = web_url(&self.url) {
+                        a href=(url) { (self.posted()) }
+                    } @else {
+                        span { (self.posted()) }
                     }
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(id: &str, parent: &str, content: &str) -> Reply {
+        Reply {
+            id: id.to_owned(),
+            in_reply_to_id: Some(parent.to_owned()),
+            post: PostData {
+                content: content.to_owned(),
+                account: AccountData {
+                    avatar_static: "https://example.com/avatar.png".to_owned(),
+                    display_name: "<b>Someone</b>".to_owned(),
+                    fqn: "someone@example.com".to_owned(),
+                    url: "https://example.com/@someone".to_owned(),
+                },
+                url: format!("https://example.com/@someone/{id}"),
+                media_attachments: vec![],
+                timestamps: Timestamps::Created {
+                    created_at: OffsetDateTime::UNIX_EPOCH,
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn reply_content_is_sanitized() {
+        let context = StatusContext {
+            descendants: vec![reply(
+                "2",
+                "1",
+                r#"<p>hi <script>alert(1)</script><img src="x" onerror="alert(1)"><a href="javascript:alert(1)">link</a></p><style>body{display:none}</style>"#,
+            )],
+        };
+        let html = context.replies_html("1").0;
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("onerror"));
+        assert!(!html.contains("javascript:"));
+        assert!(!html.contains("<style"));
+        assert!(html.contains("&lt;b&gt;Someone&lt;/b&gt;"));
+    }
+
+    #[test]
+    fn mastodon_markup_is_kept() {
+        let content = r#"<p><span class="h-card"><a href="https://example.com/@ash" class="u-url mention" onclick="x()">@<span>ash</span></a></span> hello</p>"#;
+        let html = reply("2", "1", content).post.as_html().0;
+        assert!(html.contains(r#"<span class="h-card">"#));
+        assert!(html.contains(r#"href="https://example.com/@ash""#));
+        assert!(html.contains(r#"class="u-url mention""#));
+        assert!(html.contains(r#"rel="nofollow noopener noreferrer""#));
+        assert!(!html.contains("onclick"));
+    }
+
+    #[test]
+    fn replies_are_nested_up_to_max_depth() {
+        let descendants = (2..=MAX_REPLY_DEPTH + 3)
+            .map(|i| reply(&i.to_string(), &(i - 1).to_string(), &format!("<p>{i}</p>")))
+            .collect();
+        let html = StatusContext { descendants }.replies_html("1").0;
+        assert_eq!(html.matches("<ol").count(), MAX_REPLY_DEPTH);
+        for i in 2..=MAX_REPLY_DEPTH + 3 {
+            assert!(html.contains(&format!("<p>{i}</p>")));
+        }
+    }
+
+    #[test]
+    fn statuses_are_fetched_once_at_a_time() {
+        let fetcher = CachingPostFetcher::<String>::new("");
+        assert_eq!(fetcher.lookup("example.com", "1"), (None, true));
+        // someone else is already fetching it
+        assert_eq!(fetcher.lookup("example.com", "1"), (None, false));
+    }
+
+    #[test]
+    fn failures_are_cached() {
+        let fetcher = CachingPostFetcher::<String>::new("");
+        let key = ("example.com".to_owned(), "1".to_owned());
+        let mut status = CachedStatus {
+            value: Some("old".to_owned()),
+            fetched: Some(Instant::now() - REFRESH_TIME * 2),
+            failed: true,
+            fetching: false,
+        };
+        fetcher.cache.lock().unwrap().insert(key.clone(), status);
+        // the last good copy is kept, and not fetched again until the error expires
+        assert_eq!(
+            fetcher.lookup("example.com", "1"),
+            (Some("old".into()), false)
+        );
+
+        status = CachedStatus {
+            value: Some("old".to_owned()),
+            fetched: Some(Instant::now() - ERROR_TTL * 2),
+            failed: true,
+            fetching: false,
+        };
+        fetcher.cache.lock().unwrap().insert(key.clone(), status);
+        assert_eq!(
+            fetcher.lookup("example.com", "1"),
+            (Some("old".into()), true)
+        );
+
+        status = CachedStatus {
+            value: Some("new".to_owned()),
+            fetched: Some(Instant::now() - REFRESH_TIME * 2),
+            failed: false,
+            fetching: false,
+        };
+        fetcher.cache.lock().unwrap().insert(key, status);
+        assert_eq!(
+            fetcher.lookup("example.com", "1"),
+            (Some("new".into()), true)
+        );
+    }
+
+    #[test]
+    fn attachments_without_alt_text_are_shown() {
+        let json = r#"{
+            "descendants": [{
+                "id": "2",
+                "in_reply_to_id": "1",
+                "content": "<p>look</p>",
+                "url": "https://example.com/@someone/2",
+                "created_at": "2024-01-01T00:00:00Z",
+                "edited_at": null,
+                "account": {
+                    "avatar_static": "https://example.com/avatar.png",
+                    "avatar": "https://example.com/avatar.gif",
+                    "display_name": "Someone",
+                    "fqn": "someone@example.com",
+                    "url": "https://example.com/@someone"
+                },
+                "media_attachments": [
+                    {"type": "image", "url": "https://example.com/a.png", "description": null},
+                    {"type": "image", "url": "https://example.com/b.png", "description": "a cat"}
+                ]
+            }]
+        }"#;
+        let context = serde_json::from_str::<StatusContext>(json).unwrap();
+        let html = context.replies_html("1").0;
+        assert!(html.contains(r#"<img src="https://example.com/a.png">"#));
+        assert!(html.contains(r#"<img src="https://example.com/b.png" alt="a cat">"#));
+    }
+
+    #[test]
+    fn only_web_urls_are_linked() {
+        let mut reply = reply("2", "1", "<p>hi</p>");
+        reply.post.account.avatar_static = "data:image/svg+xml,<svg></svg>".to_owned();
+        reply.post.account.url = "javascript:alert(1)".to_owned();
+        reply.post.url = "javascript:alert(2)".to_owned();
+        reply.post.media_attachments = vec![Attatchment {
+            description: None,
+            ty: "image".to_owned(),
+            url: "data:image/png;base64,AAAA".to_owned(),
+        }];
+        let html = reply.post.as_html().0;
+        assert!(!html.contains("javascript:"));
+        assert!(!html.contains("data:"));
+        assert!(!html.contains("<img"));
+        assert!(html.contains("<span>&lt;b&gt;Someone&lt;/b&gt; (@someone@example.com)</span>"));
+        assert!(html.contains("<span>Posted at 00:00 on 1970-01-01</span>"));
+    }
+}
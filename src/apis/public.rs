@@ -0,0 +1,165 @@
+//! A client for fetching URLs that other people give us, which refuses to connect to anything
+//! that isn't on the public internet, such as the tailnet or services on localhost.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use once_cell::sync::Lazy;
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect::{Attempt, Policy},
+    Client, ClientBuilder, Url,
+};
+
+use super::USER_AGENT;
+
+const MAX_REDIRECTS: usize = 5;
+
+/// Only connects to public addresses, on every redirect too. Hostnames are checked once they've
+/// been resolved, so they can't be pointed somewhere private after we've looked at them.
+pub(crate) static PUBLIC_CLIENT: Lazy<Client> = Lazy::new(|| {
+    ClientBuilder::new()
+        .user_agent(USER_AGENT)
+        .dns_resolver(PublicResolver)
+        .no_proxy()
+        .redirect(Policy::custom(check_redirect))
+        .build()
+        .expect("failed to build client")
+});
+
+/// Returns whether `url` is a web URL that `PUBLIC_CLIENT` may fetch. Hostnames are allowed
+/// here, as they are only checked when they are resolved.
+pub(crate) fn is_public_url(url: &Url) -> bool {
+    if !matches!(url.scheme(), "http" | "https") {
+        return false;
+    }
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    match host.trim_start_matches('[').trim_end_matches(']').parse() {
+        Ok(ip) => is_public_ip(ip),
+        Err(_) => true,
+    }
+}
+
+pub(crate) fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ipv4(ip),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_multicast()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        // 0.0.0.0/8, which Linux treats like localhost
+        || a == 0
+        // shared address space, which tailscale uses
+        || (a == 100 && (64..128).contains(&b))
+        // reserved, including the benchmarking range
+        || a >= 240
+        || (a == 198 && (18..20).contains(&b)))
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // unique local
+        || (first & 0xfe00) == 0xfc00
+        // link local
+        || (first & 0xffc0) == 0xfe80
+        // documentation
+        || first == 0x2001 && ip.segments()[1] == 0xdb8)
+}
+
+fn check_redirect(attempt: Attempt) -> reqwest::redirect::Action {
+    if attempt.previous().len() > MAX_REDIRECTS {
+        attempt.error("too many redirects")
+    } else if !is_public_url(attempt.url()) {
+        attempt.error("redirected to a private address")
+    } else {
+        attempt.follow()
+    }
+}
+
+/// Resolves hostnames with the system resolver, leaving out any private addresses.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_owned();
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_ip(addr.ip()))
+                .collect::<Vec<SocketAddr>>();
+            if addrs.is_empty() {
+                return Err(format!("{host} does not resolve to a public address").into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn private_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.100.100.100",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd7a:115c:a1e0::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{ip} is private");
+        }
+        for ip in ["1.1.1.1", "100.128.0.1", "2606:4700::1111"] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{ip} is public");
+        }
+    }
+
+    #[test]
+    fn urls_must_be_web_urls_on_public_hosts() {
+        let public = |url: &str| is_public_url(&Url::parse(url).unwrap());
+        assert!(public("https://example.com/post"));
+        assert!(public("http://1.1.1.1/"));
+        assert!(!public("http://127.0.0.1:3000/"));
+        assert!(!public("http://[::1]/"));
+        assert!(!public("http://169.254.169.254/latest/meta-data"));
+        assert!(!public("ftp://example.com/"));
+        assert!(!public("file:///etc/passwd"));
+    }
+
+    #[tokio::test]
+    async fn localhost_is_refused() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let result = PUBLIC_CLIENT
+            .get(format!("http://localhost:{port}/"))
+            .send()
+            .await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,300 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Instant, SystemTime},
+};
+
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    extract::Request,
+    http::{
+        header::{CONTENT_LENGTH, ETAG},
+        response::Parts,
+        Method, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_extra::headers::{ETag, HeaderMapExt, IfModifiedSince, IfNoneMatch, LastModified};
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use time::{format_description::well_known::Rfc2822, OffsetDateTime};
+
+/// `rewrite_html` leaves this element alone, and it is filled in here once the page has been
+/// hashed, so that the timestamp doesn't change the ETag on every request.
+const PAGE_GENERATED: &str = "<page-generated></page-generated>";
+
+/// The least recently requested URL is forgotten once this many have been seen, as search
+/// queries can be anything.
+const MAX_REPRESENTATIONS: usize = 1024;
+
+/// When the content of a response last changed. Handlers add this as a response extension to
+/// send it as Last-Modified; responses without one only get an ETag.
+#[derive(Clone, Copy)]
+pub struct ContentUpdated(pub OffsetDateTime);
+
+struct Representation {
+    etag: String,
+    /// When this version of the page was first served, which is shown at the bottom of pages.
+    generated: SystemTime,
+    last_requested: Instant,
+}
+
+static REPRESENTATIONS: Lazy<Mutex<HashMap<String, Representation>>> = Lazy::new(Default::default);
+
+/// Returns when the response with this ETag was first served for `url`, and whether it had been
+/// served before.
+fn generated_at(url: String, etag: &str) -> (SystemTime, bool) {
+    let mut representations = REPRESENTATIONS.lock().unwrap();
+    if let Some(representation) = representations.get_mut(&url) {
+        if representation.etag == etag {
+            representation.last_requested = Instant::now();
+            return (representation.generated, true);
+        }
+    } else if representations.len() >= MAX_REPRESENTATIONS {
+        let oldest = representations
+            .iter()
+            .min_by_key(|(_, representation)| representation.last_requested)
+            .map(|(url, _)| url.clone());
+        if let Some(oldest) = oldest {
+            representations.remove(&oldest);
+        }
+    }
+
+    let now = SystemTime::now();
+    representations.insert(
+        url,
+        Representation {
+            etag: etag.to_owned(),
+            generated: now,
+            last_requested: Instant::now(),
+        },
+    );
+    (now, false)
+}
+
+/// A strong ETag for a response body.
+pub fn etag_for(body: &[u8]) -> String {
+    format!("\"{}\"", hex::encode(&Sha256::digest(body)[..16]))
+}
+
+/// Adds the ETag and, if the handler said when its content changed, a Last-Modified to a
+/// response, returning whether the request's preconditions mean it can be answered with a 304.
+fn check_preconditions(
+    parts: &mut Parts,
+    etag: ETag,
+    unchanged: bool,
+    if_none_match: Option<IfNoneMatch>,
+    if_modified_since: Option<IfModifiedSince>,
+) -> bool {
+    // HTTP dates only have second precision, so drop the rest to keep comparisons exact
+    let last_modified = parts
+        .extensions
+        .get::<ContentUpdated>()
+        .map(|updated| SystemTime::from(updated.0.replace_nanosecond(0).unwrap()));
+
+    // If-Modified-Since is ignored when If-None-Match is present. Pages can change without their
+    // content being updated, such as when a webmention arrives, so it is only trusted if this
+    // exact page was served before.
+    let not_modified = match (if_none_match, if_modified_since, last_modified) {
+        (Some(if_none_match), _, _) => !if_none_match.precondition_passes(&etag),
+        (None, Some(if_modified_since), Some(last_modified)) => {
+            unchanged && !if_modified_since.is_modified(last_modified)
+        }
+        _ => false,
+    };
+
+    parts.headers.typed_insert(etag);
+    if let Some(last_modified) = last_modified {
+        parts
+            .headers
+            .typed_insert(LastModified::from(last_modified));
+    }
+    not_modified
+}
+
+fn not_modified(mut parts: Parts) -> Response {
+    parts.status = StatusCode::NOT_MODIFIED;
+    parts.headers.remove(CONTENT_LENGTH);
+    Response::from_parts(parts, Body::empty())
+}
+
+/// Fills in the `<page-generated>` element of an HTML page, if it has one.
+fn fill_page_generated(parts: &mut Parts, body: Bytes, generated: SystemTime) -> Body {
+    match std::str::from_utf8(&body) {
+        Ok(html) if html.contains(PAGE_GENERATED) => {
+            parts.headers.remove(CONTENT_LENGTH);
+            let generated = OffsetDateTime::from(generated)
+                .format(&Rfc2822)
+                .expect("failed to format");
+            Body::from(html.replace(PAGE_GENERATED, &generated))
+        }
+        _ => Body::from(body),
+    }
+}
+
+/// Adds a strong ETag to successful GET responses, along with a Last-Modified if the handler
+/// said when its content changed, and answers `If-None-Match`/`If-Modified-Since` with a 304
+/// when nothing has changed.
+///
+/// Handlers that already know their ETag, such as prebuilt feeds, can set it themselves to save
+/// their body being read and hashed on every request.
+pub async fn conditional_get(request: Request, next: Next) -> Response {
+    let cacheable = matches!(*request.method(), Method::GET | Method::HEAD);
+    let url = request.uri().to_string();
+    let if_none_match = request.headers().typed_get::<IfNoneMatch>();
+    let if_modified_since = request.headers().typed_get::<IfModifiedSince>();
+
+    let response = next.run(request).await;
+    // handlers that already know their ETag don't need their body read and hashed
+    if let Some(etag) = response.headers().typed_get::<ETag>() {
+        if !cacheable || response.status() != StatusCode::OK {
+            return response;
+        }
+        let etag_str = response.headers()[ETAG].to_str().unwrap_or_default();
+        let (_, unchanged) = generated_at(url, etag_str);
+        let (mut parts, body) = response.into_parts();
+        if check_preconditions(
+            &mut parts,
+            etag,
+            unchanged,
+            if_none_match,
+            if_modified_since,
+        ) {
+            return not_modified(parts);
+        }
+        return Response::from_parts(parts, body);
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            error!("Failed to read response body: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    // errors and the like aren't cached, so they're just generated now
+    if !cacheable || parts.status != StatusCode::OK {
+        let body = fill_page_generated(&mut parts, body, SystemTime::now());
+        return Response::from_parts(parts, body);
+    }
+
+    let etag = etag_for(&body);
+    let (generated, unchanged) = generated_at(url, &etag);
+    let etag = etag.parse::<ETag>().expect("hex is a valid etag");
+    if check_preconditions(
+        &mut parts,
+        etag,
+        unchanged,
+        if_none_match,
+        if_modified_since,
+    ) {
+        return not_modified(parts);
+    }
+
+    let body = fill_page_generated(&mut parts, body, generated);
+    Response::from_parts(parts, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        http::{
+            header::{IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+            HeaderValue,
+        },
+        routing::get,
+        Extension, Router,
+    };
+    use reqwest::Client;
+
+    use super::*;
+
+    const FEED: &str = "<feed>prebuilt</feed>";
+
+    fn updated() -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap()
+    }
+
+    /// Serves a page whose ETag is set by the handler, and one whose ETag isn't.
+    async fn serve() -> String {
+        let app = Router::new()
+            .route(
+                "/prebuilt",
+                get(|| async {
+                    (
+                        [(ETAG, HeaderValue::from_static("\"prebuilt\""))],
+                        Extension(ContentUpdated(updated())),
+                        FEED,
+                    )
+                }),
+            )
+            .route(
+                "/hashed",
+                get(|| async { (Extension(ContentUpdated(updated())), FEED) }),
+            )
+            .layer(axum::middleware::from_fn(conditional_get));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{addr}")
+    }
+
+    #[tokio::test]
+    async fn etag_is_the_hash_of_the_body() {
+        let server = serve().await;
+        let response = Client::new()
+            .get(format!("{server}/hashed"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[ETAG], etag_for(FEED.as_bytes()));
+        assert_eq!(
+            response.headers()[LAST_MODIFIED],
+            "Tue, 14 Nov 2023 22:13:20 GMT"
+        );
+    }
+
+    #[tokio::test]
+    async fn prebuilt_etag_is_kept() {
+        let server = serve().await;
+        let client = Client::new();
+        let url = format!("{server}/prebuilt");
+
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[ETAG], "\"prebuilt\"");
+        assert_eq!(
+            response.headers()[LAST_MODIFIED],
+            "Tue, 14 Nov 2023 22:13:20 GMT"
+        );
+        assert_eq!(response.text().await.unwrap(), FEED);
+
+        let response = client
+            .get(&url)
+            .header(IF_NONE_MATCH, "\"prebuilt\"")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[ETAG], "\"prebuilt\"");
+
+        let response = client
+            .get(&url)
+            .header(IF_NONE_MATCH, "\"other\"")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = client
+            .get(&url)
+            .header(IF_MODIFIED_SINCE, "Tue, 14 Nov 2023 22:13:20 GMT")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+}
@@ -0,0 +1,354 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, RwLock},
+};
+
+use time::OffsetDateTime;
+
+use crate::{
+    error::{ContentError, ContentErrors},
+    routes::{
+        blog::{self, BlogAssets, BlogPost, PreviewKey},
+        feeds::{FeedEntry, FeedInfo, FeedPost, Feeds},
+        projects::{self, Project, ProjectsAssets},
+    },
+    search::{DocumentKind, SearchIndex, SearchResult},
+};
+
+/// All of the markdown content embedded into the binary, parsed and rendered once at startup.
+pub struct ContentIndex {
+    /// Every blog post, newest first, including drafts and scheduled posts.
+    posts: Vec<FeedPost>,
+    /// Projects, newest year first.
+    pub projects: Vec<Arc<Project>>,
+    pub project_feeds: Feeds,
+    posts_by_path: HashMap<String, Arc<BlogPost>>,
+    projects_by_path: HashMap<String, usize>,
+    published: RwLock<Arc<PublishedPosts>>,
+    /// Maximum number of entries in each feed, or each page of an atom feed.
+    feed_page_size: usize,
+    preview_key: Option<PreviewKey>,
+    search: SearchIndex,
+}
+
+/// The posts that are currently visible to the public, and everything derived from them.
+pub struct PublishedPosts {
+    /// Newest first.
+    pub posts: Vec<Arc<BlogPost>>,
+    pub feeds: Feeds,
+    /// Every tag used by a published post, in alphabetical order.
+    pub tags: BTreeMap<String, Tag>,
+    /// Every series with a published post, with its parts in order.
+    pub series: BTreeMap<String, Vec<Arc<BlogPost>>>,
+    /// Links to other posts to show at the end of each published post, by URL.
+    pub navigation: HashMap<String, Navigation>,
+    /// When the next scheduled post goes live, at which point this needs rebuilding.
+    next_change: Option<OffsetDateTime>,
+}
+
+/// How many related posts to suggest at the end of a post.
+const RELATED_POSTS: usize = 3;
+
+pub struct Navigation {
+    pub newer: Option<Arc<BlogPost>>,
+    pub older: Option<Arc<BlogPost>>,
+    /// Most related first.
+    pub related: Vec<Arc<BlogPost>>,
+}
+
+impl Navigation {
+    /// Builds the navigation for the post at `index` in `posts`, which are sorted newest first.
+    fn build(posts: &[Arc<BlogPost>], index: usize) -> Self {
+        let post = &posts[index];
+        let mut related = posts
+            .iter()
+            .filter(|other| !Arc::ptr_eq(other, post))
+            .map(|other| (relatedness(post, other), other))
+            .filter(|(score, _)| *score > 0)
+            .collect::<Vec<_>>();
+        // stable sort, so ties go to the newest post
+        related.sort_by_key(|(score, _)| Reverse(*score));
+
+        Self {
+            newer: index.checked_sub(1).map(|i| posts[i].clone()),
+            older: posts.get(index + 1).cloned(),
+            related: related
+                .into_iter()
+                .take(RELATED_POSTS)
+                .map(|(_, post)| post.clone())
+                .collect(),
+        }
+    }
+}
+
+/// Scores how related two posts are, by the tags and links they have in common, and whether
+/// either links to the other.
+fn relatedness(a: &BlogPost, b: &BlogPost) -> usize {
+    let shared_tags = a.tags.iter().filter(|tag| b.tags.contains(tag)).count();
+    let shared_links = a
+        .links
+        .iter()
+        .filter(|link| b.links.binary_search(link).is_ok())
+        .count();
+    let linked = a.links.binary_search(&b.url()).is_ok() || b.links.binary_search(&a.url()).is_ok();
+    shared_tags * 2 + shared_links + if linked { 3 } else { 0 }
+}
+
+pub struct Tag {
+    /// Newest first.
+    pub posts: Vec<Arc<BlogPost>>,
+    pub feeds: Feeds,
+}
+
+impl PublishedPosts {
+    fn build(all_posts: &[FeedPost], now: OffsetDateTime, feed_page_size: usize) -> Self {
+        let feed_posts = all_posts
+            .iter()
+            .filter(|post| post.post.is_published(now))
+            .collect::<Vec<_>>();
+        let feeds = Feeds::build(&FeedInfo::blog(), &feed_posts, feed_page_size);
+
+        let mut tagged_posts = BTreeMap::<String, Vec<&FeedPost>>::new();
+        for post in &feed_posts {
+            for tag in &post.post.tags {
+                tagged_posts.entry(tag.clone()).or_default().push(post);
+            }
+        }
+        let tags = tagged_posts
+            .into_iter()
+            .map(|(name, posts)| {
+                let feeds = Feeds::build(&FeedInfo::tag(&name), &posts, feed_page_size);
+                let posts = posts.iter().map(|post| post.post.clone()).collect();
+                (name, Tag { posts, feeds })
+            })
+            .collect();
+
+        let mut series = BTreeMap::<String, Vec<Arc<BlogPost>>>::new();
+        // oldest first, so unnumbered parts end up in the order they were posted
+        for post in feed_posts.iter().rev() {
+            if let Some(name) = &post.post.series {
+                series
+                    .entry(name.clone())
+                    .or_default()
+                    .push(post.post.clone());
+            }
+        }
+        for parts in series.values_mut() {
+            parts.sort_by_key(|post| post.series_part.unwrap_or(u32::MAX));
+        }
+
+        let posts = feed_posts
+            .iter()
+            .map(|post| post.post.clone())
+            .collect::<Vec<_>>();
+        let navigation = posts
+            .iter()
+            .enumerate()
+            .map(|(i, post)| (post.url(), Navigation::build(&posts, i)))
+            .collect();
+
+        let next_change = all_posts
+            .iter()
+            .filter(|post| !post.post.draft)
+            .filter_map(|post| post.post.publish_at)
+            .filter(|&publish_at| publish_at > now)
+            .min();
+
+        Self {
+            posts,
+            feeds,
+            tags,
+            series,
+            navigation,
+            next_change,
+        }
+    }
+}
+
+/// Skips over broken files at startup, which `website check` should have caught before deploying.
+fn log_content_error<T>(result: Result<T, ContentErrors>) -> Option<T> {
+    result
+        .map_err(|errors| {
+            for e in errors.0 {
+                error!("skipping broken content: {e}");
+            }
+        })
+        .ok()
+}
+
+/// Loads every file in `blog/` and `projects/`, returning all of the problems found, including
+/// ones that the server would work around.
+pub fn check() -> Vec<ContentError> {
+    let mut problems = vec![];
+    for path in BlogAssets::iter() {
+        if let Err(errors) = blog::load_post(&path, &mut problems) {
+            problems.extend(errors.0);
+        }
+    }
+    for path in ProjectsAssets::iter() {
+        if let Err(errors) = projects::load_project(&path, &mut problems) {
+            problems.extend(errors.0);
+        }
+    }
+    problems
+}
+
+impl ContentIndex {
+    pub async fn load(preview_key: Option<PreviewKey>, feed_page_size: usize) -> Arc<Self> {
+        let mut warnings = vec![];
+        let mut posts = BlogAssets::iter()
+            .filter_map(|path| log_content_error(blog::load_post(&path, &mut warnings)))
+            .map(Arc::new)
+            .collect::<Vec<_>>();
+        posts.sort_by_key(|p| p.date());
+        posts.reverse();
+
+        let mut projects = ProjectsAssets::iter()
+            .filter_map(|path| log_content_error(projects::load_project(&path, &mut warnings)))
+            .map(Arc::new)
+            .collect::<Vec<_>>();
+        for warning in warnings {
+            warn!("{warning}");
+        }
+        // stable sort, so projects within a year stay in filename order
+        projects.sort_by(|a, b| b.year.cmp(&a.year));
+
+        let posts_by_path = posts
+            .iter()
+            .map(|post| (post.url(), post.clone()))
+            .collect();
+        let projects_by_path = projects
+            .iter()
+            .enumerate()
+            .map(|(i, project)| (project.url(), i))
+            .collect();
+
+        let mut search = SearchIndex::default();
+        for post in &posts {
+            search.add(
+                DocumentKind::Post,
+                post.url(),
+                &post.title,
+                &post.description,
+                &post.rendered,
+            );
+        }
+        for project in &projects {
+            search.add(
+                DocumentKind::Project,
+                project.url(),
+                &project.title,
+                &project.description,
+                &project.rendered,
+            );
+        }
+
+        let posts = FeedPost::render_all(&posts).await;
+
+        let mut feed_projects = FeedPost::render_all(&projects).await;
+        feed_projects.sort_by_key(|project| Reverse(project.post.published()));
+        let project_feeds = Feeds::build(
+            &FeedInfo::projects(),
+            &feed_projects.iter().collect::<Vec<_>>(),
+            feed_page_size,
+        );
+
+        let now = OffsetDateTime::now_utc();
+        let published = PublishedPosts::build(&posts, now, feed_page_size);
+
+        info!(
+            "loaded {} blog posts ({} unpublished) and {} projects",
+            posts.len(),
+            posts.len() - published.posts.len(),
+            projects.len()
+        );
+
+        Arc::new(Self {
+            posts,
+            projects,
+            project_feeds,
+            posts_by_path,
+            projects_by_path,
+            published: RwLock::new(Arc::new(published)),
+            feed_page_size,
+            preview_key,
+            search,
+        })
+    }
+
+    /// Returns the currently published posts, rebuilding them first if a scheduled post has
+    /// gone live since they were last built.
+    pub fn published(&self) -> Arc<PublishedPosts> {
+        let now = OffsetDateTime::now_utc();
+        let is_stale = |published: &PublishedPosts| {
+            published
+                .next_change
+                .is_some_and(|next_change| next_change <= now)
+        };
+
+        {
+            let published = self.published.read().unwrap();
+            if !is_stale(&published) {
+                return published.clone();
+            }
+        }
+
+        let mut published = self.published.write().unwrap();
+        if is_stale(&published) {
+            *published = Arc::new(PublishedPosts::build(&self.posts, now, self.feed_page_size));
+            info!("rebuilt published posts, now {}", published.posts.len());
+        }
+        published.clone()
+    }
+
+    /// The currently published posts with their content prepared for feeds, newest first.
+    pub fn published_feed_posts(&self) -> Vec<&FeedPost> {
+        let now = OffsetDateTime::now_utc();
+        self.posts
+            .iter()
+            .filter(|post| post.post.is_published(now))
+            .collect()
+    }
+
+    /// Looks up a post by URL, whether or not it has been published yet.
+    pub fn post(&self, url: &str) -> Option<&Arc<BlogPost>> {
+        self.posts_by_path.get(url)
+    }
+
+    /// Signed preview links for every post that isn't published yet, by post URL. These aren't
+    /// logged, as anyone with the logs could use them.
+    pub fn preview_links(&self) -> Vec<(String, String)> {
+        let Some(preview_key) = &self.preview_key else {
+            return vec![];
+        };
+        let now = OffsetDateTime::now_utc();
+        self.posts
+            .iter()
+            .filter(|post| !post.post.is_published(now))
+            .map(|post| {
+                let url = post.post.url();
+                let link = preview_key.preview_url(&url);
+                (url, link)
+            })
+            .collect()
+    }
+
+    pub fn verify_preview(&self, url: &str, token: &str) -> bool {
+        self.preview_key
+            .as_ref()
+            .is_some_and(|key| key.verify(url, token))
+    }
+
+    /// Searches published posts and projects.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchResult> {
+        let now = OffsetDateTime::now_utc();
+        self.search.search(query, limit, |url| {
+            self.post(url).is_none_or(|post| post.is_published(now))
+        })
+    }
+
+    pub fn project(&self, url: &str) -> Option<&Arc<Project>> {
+        self.projects_by_path.get(url).map(|&i| &self.projects[i])
+    }
+}
@@ -0,0 +1,322 @@
+use std::{
+    collections::HashMap,
+    panic::{self, AssertUnwindSafe},
+    sync::Mutex,
+};
+
+use layout::{
+    core::{
+        color::Color,
+        format::{ClipHandle, RenderBackend},
+        geometry::Point,
+        style::StyleAttr,
+    },
+    gv::{DotParser, GraphBuilder},
+};
+use maud::{html, Markup};
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+
+/// Rendered diagrams keyed by the SHA-256 of their source, so unchanged diagrams are only laid out once.
+static DIAGRAMS: Lazy<Mutex<HashMap<[u8; 32], String>>> = Lazy::new(Default::default);
+
+/// The colours layout-rs uses when the source doesn't set one, which are left to the stylesheet
+/// so diagrams follow the site palette.
+const DEFAULT_LINE: &str = "#000000ff";
+const DEFAULT_FILL: &str = "#ffffffff";
+
+/// Lays out a graphviz `dot` graph and renders it to an inline SVG.
+///
+/// The SVG has no ids or embedded styles, so any number of diagrams can share a page (or a feed
+/// entry), and elements without an explicit colour are styled by the `.diagram` rules in
+/// `style.css`.
+pub fn render(source: &str) -> Result<String, String> {
+    let hash: [u8; 32] = Sha256::digest(source.as_bytes()).into();
+    if let Some(svg) = DIAGRAMS.lock().unwrap().get(&hash) {
+        return Ok(svg.clone());
+    }
+
+    // layout-rs panics on some malformed input instead of returning an error
+    let mut writer = SvgWriter::default();
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        let graph = DotParser::new(source).process()?;
+        let mut builder = GraphBuilder::new();
+        builder.visit_graph(&graph);
+        builder.get().do_it(false, false, false, &mut writer);
+        Ok::<_, String>(())
+    }))
+    .map_err(|_| "failed to lay out the graph".to_owned())??;
+
+    let svg = writer.finish();
+    DIAGRAMS.lock().unwrap().insert(hash, svg.clone());
+    Ok(svg)
+}
+
+#[derive(Default)]
+struct SvgWriter {
+    content: String,
+    width: f64,
+    height: f64,
+}
+
+impl SvgWriter {
+    fn grow(&mut self, point: Point, size: Point) {
+        self.width = self.width.max(point.x + size.x + 5.);
+        self.height = self.height.max(point.y + size.y + 5.);
+    }
+
+    fn push(&mut self, markup: Markup) {
+        self.content.push_str(&markup.into_string());
+    }
+
+    fn finish(self) -> String {
+        let (width, height) = (round(self.width), round(self.height));
+        let markup = html! {
+            svg xmlns="http://www.w3.org/2000/svg" width=(width) height=(height) viewBox={ "0 0 " (width) " " (height) } {
+                (maud::PreEscaped(self.content))
+            }
+        };
+        markup.into_string()
+    }
+}
+
+impl RenderBackend for SvgWriter {
+    fn draw_rect(&mut self, xy: Point, size: Point, look: &StyleAttr, _clip: Option<ClipHandle>) {
+        // clip paths would need ids, and only round the corners of records
+        self.grow(xy, size);
+        self.push(html! {
+            rect x=(round(xy.x)) y=(round(xy.y)) width=(round(size.x)) height=(round(size.y))
+                rx=[(look.rounded > 0).then_some(look.rounded)]
+                fill=[fill(look)] stroke=[stroke(look.line_color)] stroke-width=(look.line_width) {}
+        });
+    }
+
+    fn draw_line(&mut self, start: Point, stop: Point, look: &StyleAttr) {
+        self.push(html! {
+            line x1=(round(start.x)) y1=(round(start.y)) x2=(round(stop.x)) y2=(round(stop.y))
+                stroke=[stroke(look.line_color)] stroke-width=(look.line_width) {}
+        });
+    }
+
+    fn draw_circle(&mut self, xy: Point, size: Point, look: &StyleAttr) {
+        self.grow(xy, size);
+        self.push(html! {
+            ellipse cx=(round(xy.x)) cy=(round(xy.y)) rx=(round(size.x / 2.)) ry=(round(size.y / 2.))
+                fill=[fill(look)] stroke=[stroke(look.line_color)] stroke-width=(look.line_width) {}
+        });
+    }
+
+    fn draw_text(&mut self, xy: Point, text: &str, look: &StyleAttr) {
+        let lines = text.lines().collect::<Vec<_>>();
+        let font_size = look.font_size as f64;
+        let height = lines.len() as f64 * font_size;
+        // the font is up to the browser, so this is only a rough guess
+        let width = lines
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or_default() as f64
+            * font_size
+            * 0.6;
+        // text on a filled shape is coloured like its outline, as the palette may not contrast with it
+        let colour = fill(look)
+            .filter(|fill| fill != "none")
+            .map(|_| look.line_color.to_web_color());
+        self.grow(
+            Point::new(xy.x - width / 2., xy.y - height / 2.),
+            Point::new(width, height),
+        );
+        // each line is centred on its baseline, so the block is centred on `xy` by starting half
+        // a line up for every line after the first
+        let y = xy.y - lines.len().saturating_sub(1) as f64 * font_size / 2.;
+        self.push(html! {
+            text x=(round(xy.x)) y=(round(y)) font-size=(look.font_size) fill=[colour]
+                text-anchor="middle" dominant-baseline="middle" {
+                @for (i, line) in lines.iter().enumerate() {
+                    tspan x=(round(xy.x)) dy=[(i > 0).then_some("1em")] { (line) }
+                }
+            }
+        });
+    }
+
+    fn draw_arrow(
+        &mut self,
+        path: &[(Point, Point)],
+        dashed: bool,
+        head: (bool, bool),
+        look: &StyleAttr,
+        text: &str,
+    ) {
+        if path.len() < 2 {
+            return;
+        }
+        for (point, control) in path {
+            self.grow(*point, Point::zero());
+            self.grow(*control, Point::zero());
+        }
+
+        // The first pair is the start point and its control point, the rest are a control point
+        // followed by the next point on the curve.
+        let mut d = format!(
+            "M {} {} C {} {}, {} {}, {} {}",
+            round(path[0].0.x),
+            round(path[0].0.y),
+            round(path[0].1.x),
+            round(path[0].1.y),
+            round(path[1].0.x),
+            round(path[1].0.y),
+            round(path[1].1.x),
+            round(path[1].1.y)
+        );
+        for (control, point) in &path[2..] {
+            d.push_str(&format!(
+                " S {} {}, {} {}",
+                round(control.x),
+                round(control.y),
+                round(point.x),
+                round(point.y)
+            ));
+        }
+
+        let colour = stroke(look.line_color);
+        self.push(html! {
+            path.diagram-edge d=(d) fill="none" stroke=[&colour] stroke-width=(look.line_width)
+                stroke-dasharray=[dashed.then_some("5,5")] {}
+        });
+
+        // Arrowheads are drawn as triangles rather than markers, which would need ids.
+        let last = path[path.len() - 1];
+        let heads = [(head.0, path[0].0, path[0].1), (head.1, last.1, last.0)];
+        for (_, tip, control) in heads.into_iter().filter(|(draw, ..)| *draw) {
+            let (dx, dy) = (tip.x - control.x, tip.y - control.y);
+            let length = dx.hypot(dy);
+            let (dx, dy) = if length > 0. {
+                (dx / length, dy / length)
+            } else {
+                (0., 1.)
+            };
+            let (bx, by) = (tip.x - dx * 10., tip.y - dy * 10.);
+            let points = format!(
+                "{},{} {},{} {},{}",
+                round(tip.x),
+                round(tip.y),
+                round(bx - dy * 3.5),
+                round(by + dx * 3.5),
+                round(bx + dy * 3.5),
+                round(by - dx * 3.5),
+            );
+            self.push(html! {
+                polygon.diagram-arrowhead points=(points) fill=[&colour] {}
+            });
+        }
+
+        if !text.is_empty() {
+            let middle = path[path.len() / 2].0;
+            self.draw_text(middle, text, look);
+        }
+    }
+
+    fn create_clip(&mut self, _xy: Point, _size: Point, _rounded_px: usize) -> ClipHandle {
+        0
+    }
+}
+
+fn round(x: f64) -> f64 {
+    (x * 10.).round() / 10.
+}
+
+fn stroke(colour: Color) -> Option<String> {
+    let colour = colour.to_web_color();
+    (colour != DEFAULT_LINE).then_some(colour)
+}
+
+fn fill(look: &StyleAttr) -> Option<String> {
+    match look.fill_color {
+        Some(colour) => {
+            let colour = colour.to_web_color();
+            (colour != DEFAULT_FILL).then_some(colour)
+        }
+        None => Some("none".to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(fill_color: Option<Color>) -> StyleAttr {
+        StyleAttr::new(Color::fast("black"), 2, fill_color, 0, 10)
+    }
+
+    #[test]
+    fn renders_svg() {
+        let svg = render("digraph { a -> b; }").unwrap();
+        assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg""#));
+        assert!(svg.contains("viewBox=\"0 0 "));
+        assert!(svg.contains(">a</tspan>"));
+        assert!(svg.contains(">b</tspan>"));
+        assert!(svg.contains(r#"class="diagram-edge""#));
+        assert!(svg.contains(r#"class="diagram-arrowhead""#));
+        // no ids, so diagrams can share a page
+        assert!(!svg.contains("id="));
+        // cached diagrams are the same
+        assert_eq!(render("digraph { a -> b; }").unwrap(), svg);
+    }
+
+    #[test]
+    fn malformed_input_is_an_error() {
+        assert!(render("digraph {").is_err());
+        assert!(render("not a graph").is_err());
+        assert!(render("digraph { a -> }").is_err());
+        // layout-rs panics on empty graphs
+        assert_eq!(
+            render("digraph { }").unwrap_err(),
+            "failed to lay out the graph"
+        );
+    }
+
+    #[test]
+    fn default_colours_are_left_to_the_stylesheet() {
+        let svg = render("digraph { a -> b; }").unwrap();
+        assert!(!svg.contains(DEFAULT_LINE));
+        assert!(!svg.contains(DEFAULT_FILL));
+        assert!(!svg.contains("stroke=\"#"));
+
+        let svg = render(r#"digraph { a [color="red", fillcolor="blue", style=filled]; a -> b [color="green"]; }"#)
+            .unwrap();
+        assert!(svg.contains("stroke=\"#ff0000ff\""));
+        assert!(svg.contains("fill=\"#0000ffff\""));
+        assert!(svg.contains("stroke=\"#008000ff\""));
+    }
+
+    #[test]
+    fn text_is_centred_on_its_position() {
+        let mut writer = SvgWriter::default();
+        writer.draw_text(Point::new(100., 50.), "one\ntwo\nthree", &style(None));
+        let svg = writer.content;
+        // three lines of 10px, so the first is a line above the middle one
+        assert!(svg.contains(r#"<text x="100" y="40" font-size="10""#));
+        assert!(svg.contains(r#"<tspan x="100">one</tspan>"#));
+        assert!(svg.contains(r#"<tspan x="100" dy="1em">two</tspan>"#));
+        assert!(svg.contains(r#"<tspan x="100" dy="1em">three</tspan>"#));
+        // five characters wide and three lines tall, plus the margin
+        assert_eq!(writer.width, 100. + 15. + 5.);
+        assert_eq!(writer.height, 50. + 15. + 5.);
+
+        let mut writer = SvgWriter::default();
+        writer.draw_text(Point::new(100., 50.), "one", &style(None));
+        assert!(writer.content.contains(r#"y="50""#));
+        assert!(!writer.content.contains("dy="));
+    }
+
+    #[test]
+    fn text_on_filled_shapes_uses_the_line_colour() {
+        let mut writer = SvgWriter::default();
+        writer.draw_text(Point::new(0., 0.), "a", &style(Some(Color::fast("red"))));
+        assert!(writer.content.contains(r##"fill="#000000ff""##));
+
+        let mut writer = SvgWriter::default();
+        writer.draw_text(Point::new(0., 0.), "a", &style(None));
+        assert!(!writer.content.contains("fill="));
+    }
+}
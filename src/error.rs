@@ -2,6 +2,67 @@
 pub enum WebsiteError {
     #[error("reqwest error: {0}")]
     ReqwestError(#[from] reqwest::Error),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("activitypub error: {0}")]
+    ActivityPub(&'static str),
+    #[error("invalid http signature: {0}")]
+    Signature(&'static str),
+    #[error("refusing to fetch a private address")]
+    PrivateUrl,
 }
 
 pub type Result<T> = std::result::Result<T, WebsiteError>;
+
+/// A problem with one of the markdown files in `blog/` or `projects/`.
+#[derive(Debug, thiserror::Error)]
+#[error("{file}:{line}: {kind}")]
+pub struct ContentError {
+    pub file: String,
+    /// Problems with the file as a whole are reported on the first line.
+    pub line: usize,
+    #[source]
+    pub kind: ContentErrorKind,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ContentErrorKind {
+    #[error("file not found")]
+    Missing,
+    #[error("filename should look like `{0}`")]
+    Filename(&'static str),
+    #[error("invalid date in filename: {0}")]
+    Date(#[from] time::error::ComponentRange),
+    #[error("file isn't valid UTF-8")]
+    Utf8,
+    #[error("missing `+++` frontmatter")]
+    MissingFrontmatter,
+    #[error("invalid frontmatter: {0}")]
+    Frontmatter(String),
+    #[error("invalid math: {0}")]
+    Math(String),
+    #[error("invalid diagram: {0}")]
+    Diagram(String),
+}
+
+/// Every problem found in one file, in the order they appear.
+#[derive(Debug)]
+pub struct ContentErrors(pub Vec<ContentError>);
+
+impl From<ContentError> for ContentErrors {
+    fn from(error: ContentError) -> Self {
+        Self(vec![error])
+    }
+}
+
+impl ContentErrorKind {
+    pub fn at(self, file: &str, line: usize) -> ContentError {
+        ContentError {
+            file: file.to_owned(),
+            line,
+            kind: self,
+        }
+    }
+}
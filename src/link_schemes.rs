@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use reqwest::Url;
+use rust_embed::RustEmbed;
+use serde::Deserialize;
+
+#[derive(RustEmbed)]
+#[folder = "link-schemes/"]
+struct LinkSchemeAssets;
+
+/// A custom URL scheme for links in markdown, configured in `link-schemes/schemes.toml`.
+#[derive(Deserialize)]
+struct LinkScheme {
+    /// Where the link goes, with `{host}` and `{path}` filled in from the link.
+    url: String,
+    default_host: Option<String>,
+    icon: Option<String>,
+}
+
+/// A link scheme with its icon loaded.
+struct LoadedScheme {
+    scheme: LinkScheme,
+    icon: Option<String>,
+}
+
+static LINK_SCHEMES: Lazy<HashMap<String, LoadedScheme>> = Lazy::new(|| {
+    let config = LinkSchemeAssets::get("schemes.toml").expect("missing schemes.toml");
+    let config = std::str::from_utf8(&config.data).expect("schemes.toml isn't UTF-8");
+    let schemes: HashMap<String, LinkScheme> =
+        toml::from_str(config).expect("failed to parse schemes.toml");
+
+    schemes
+        .into_iter()
+        .map(|(name, scheme)| {
+            let icon = scheme.icon.as_ref().map(|icon| {
+                self::icon(icon)
+                    .unwrap_or_else(|| panic!("missing icon `{icon}` for `{name}://` links"))
+            });
+            (name, LoadedScheme { scheme, icon })
+        })
+        .collect()
+});
+
+/// Loads an SVG from `link-schemes/icons/`, which are also used by other parts of markdown.
+pub fn icon(name: &str) -> Option<String> {
+    let svg = LinkSchemeAssets::get(&format!("icons/{name}.svg"))?;
+    Some(String::from_utf8_lossy(&svg.data).trim().to_owned())
+}
+
+/// Expands a link using a custom scheme into the real URL, along with the scheme's icon if it
+/// has one. Returns `None` for links that don't use a custom scheme.
+pub fn expand(link: &str) -> Option<(String, Option<&'static str>)> {
+    let url = Url::parse(link).ok()?;
+    let LoadedScheme { scheme, icon } = LINK_SCHEMES.get(url.scheme())?;
+
+    let host = url
+        .host_str()
+        .filter(|host| !host.is_empty())
+        .or(scheme.default_host.as_deref())
+        .unwrap_or_default();
+    let mut path = url.path().to_owned();
+    if let Some(query) = url.query() {
+        path.push('?');
+        path.push_str(query);
+    }
+    if let Some(fragment) = url.fragment() {
+        path.push('#');
+        path.push_str(fragment);
+    }
+
+    let expanded = scheme.url.replace("{host}", host).replace("{path}", &path);
+    Some((expanded, icon.as_deref()))
+}
@@ -1,12 +1,21 @@
 #[macro_use]
 extern crate tracing;
 
+mod activitypub;
 mod apis;
 mod assets;
+mod conditional;
+mod content;
+mod diagram;
 mod error;
+mod link_schemes;
 mod markdown;
+mod math;
 mod routes;
+mod search;
+mod storage;
 mod templates;
+mod webmention;
 
 #[cfg(debug_assertions)]
 use std::path::Path;
@@ -38,10 +47,19 @@ async fn main() -> error::Result<()> {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let pronouns_page_client =
-        CachingFetcher::<PronounsPageProfile>::new(PRONOUNS_PAGE_URL.to_string()).await?;
-    let nowplaying_client =
-        CachingFetcher::<NowPlayingInfo>::new(NOWPLAYING_URL.to_string()).await?;
+    // `website check` validates the content without starting the server, to catch problems before deploying
+    if std::env::args().nth(1).as_deref() == Some("check") {
+        let problems = content::check();
+        for problem in &problems {
+            eprintln!("{problem}");
+        }
+        if !problems.is_empty() {
+            eprintln!("found {} problem(s)", problems.len());
+            std::process::exit(1);
+        }
+        println!("all content is valid");
+        return Ok(());
+    }
 
     //let mediawiki_client = MediawikiClient::new(
     //    "wiki.ashhhleyyy.dev".to_owned(),
@@ -59,7 +77,44 @@ async fn main() -> error::Result<()> {
     //    )
     //    .await?;
 
+    let preview_key = std::env::var("PREVIEW_SECRET")
+        .ok()
+        .map(|secret| routes::blog::PreviewKey::new(&secret));
+    let feed_page_size: usize = std::env::var("FEED_PAGE_SIZE")
+        .unwrap_or_else(|_| "20".to_string())
+        .parse()
+        .expect("failed to parse `FEED_PAGE_SIZE` environment variable");
+    assert!(feed_page_size > 0, "`FEED_PAGE_SIZE` must be at least 1");
+    let content = content::ContentIndex::load(preview_key, feed_page_size).await;
+
+    // `website preview-links` prints the links for sharing unpublished posts
+    if std::env::args().nth(1).as_deref() == Some("preview-links") {
+        if std::env::var_os("PREVIEW_SECRET").is_none() {
+            eprintln!("`PREVIEW_SECRET` must be set to make preview links");
+            std::process::exit(1);
+        }
+        for (url, link) in content.preview_links() {
+            println!("{url}: {link}");
+        }
+        return Ok(());
+    }
+
+    let pronouns_page_client =
+        CachingFetcher::<PronounsPageProfile>::new(PRONOUNS_PAGE_URL.to_string()).await?;
+    let nowplaying_client =
+        CachingFetcher::<NowPlayingInfo>::new(NOWPLAYING_URL.to_string()).await?;
+
+    let webmention_dir = std::env::var("WEBMENTION_DIR").unwrap_or_else(|_| "data".to_string());
+    let webmentions = webmention::Webmentions::start(webmention_dir.into()).await?;
+    let activitypub_dir = std::env::var("ACTIVITYPUB_DIR").unwrap_or_else(|_| "data".to_string());
+    let activitypub =
+        activitypub::ActivityPub::start(activitypub_dir.into(), content.clone()).await?;
+
     let app = routes::build_router()
+        .layer(axum::middleware::from_fn(conditional::conditional_get))
+        .layer(Extension(content))
+        .layer(Extension(webmentions))
+        .layer(Extension(activitypub))
         .layer(Extension(pronouns_page_client))
         .layer(Extension(nowplaying_client));
     //.layer(Extension(mediawiki_client));
@@ -1,36 +1,315 @@
-use std::cell::RefCell;
+use std::{cell::RefCell, collections::HashMap, fmt::Write};
 
 use comrak::{
-    format_html_with_plugins,
-    nodes::{Ast, AstNode, NodeValue},
+    create_formatter,
+    html::format_node_default,
+    nodes::{AlertType, Ast, AstNode, LineColumn, NodeHtmlBlock, NodeValue},
     parse_document,
-    plugins::syntect::SyntectAdapter,
+    plugins::syntect::{SyntectAdapter, SyntectAdapterBuilder},
     Arena,
 };
 use extract_frontmatter::{config::Splitter, Extractor};
+use maud::{html, PreEscaped};
 use once_cell::sync::Lazy;
-use regex::{Captures, Regex};
-use reqwest::Url;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+use time::{Date, Month, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
 
-static ICON_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"!--icon\((.*)\)--!").unwrap());
+use crate::{
+    diagram,
+    error::{ContentError, ContentErrorKind, ContentErrors},
+    link_schemes, math,
+};
 
 #[derive(Deserialize)]
 pub struct Metadata {
     pub title: String,
     pub description: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Insert a table of contents at the start of the page.
+    #[serde(default)]
+    pub toc: bool,
+    /// Name of the series this post belongs to, used in its URL.
+    pub series: Option<String>,
+    /// Where this post comes in its series. Parts without a number are ordered by date.
+    pub series_part: Option<u32>,
+    #[serde(default)]
+    pub draft: bool,
+    #[serde(default, deserialize_with = "deserialize_datetime")]
+    pub publish_at: Option<OffsetDateTime>,
+    /// When a project was made, as project filenames only include the year.
+    #[serde(default, deserialize_with = "deserialize_datetime")]
+    pub date: Option<OffsetDateTime>,
+    /// When the page was last changed in a meaningful way.
+    #[serde(default, deserialize_with = "deserialize_datetime")]
+    pub updated: Option<OffsetDateTime>,
+    #[serde(default)]
+    pub changelog: Vec<ChangelogEntry>,
+    /// The server and ID of the fediverse post announcing this one, written as `server/id`,
+    /// whose replies are shown as comments.
+    #[serde(default, deserialize_with = "deserialize_comments")]
+    pub comments: Option<(String, String)>,
 }
 
-pub fn render_markdown(markdown: &str) -> (Metadata, String) {
-    let (frontmatter, body) = Extractor::new(Splitter::EnclosingLines("+++")).extract(markdown);
+fn deserialize_comments<'de, D>(deserializer: D) -> Result<Option<(String, String)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
 
-    let metadata = toml::from_str(&frontmatter).unwrap_or_else(|e| {
-        error!("failed to parse frontmatter: {e}");
-        Metadata {
-            title: "WARNING! An error occured while parsing the frontmatter".to_owned(),
-            description: "WARNING! An error occured while parsing the frontmatter".to_owned(),
+    let Some(comments) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    match comments.split_once('/') {
+        Some((server, id)) if !server.is_empty() && !id.is_empty() => {
+            Ok(Some((server.to_owned(), id.to_owned())))
         }
-    });
+        _ => Err(D::Error::custom(
+            "`comments` should be in the form `server/id`",
+        )),
+    }
+}
+
+/// A note about an edit made to a post after it was published.
+#[derive(Deserialize)]
+pub struct ChangelogEntry {
+    #[serde(deserialize_with = "deserialize_required_datetime")]
+    pub date: OffsetDateTime,
+    pub note: String,
+}
+
+fn deserialize_required_datetime<'de, D>(deserializer: D) -> Result<OffsetDateTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    deserialize_datetime(deserializer)?.ok_or_else(|| D::Error::custom("missing datetime"))
+}
+
+/// Deserializes a TOML datetime into an [`OffsetDateTime`].
+///
+/// Dates without a time are treated as midnight, and datetimes without an offset as UTC.
+fn deserialize_datetime<'de, D>(deserializer: D) -> Result<Option<OffsetDateTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+    use toml::value::{Datetime, Offset};
+
+    let Some(datetime) = Option::<Datetime>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+
+    let date = datetime
+        .date
+        .ok_or_else(|| D::Error::custom("datetime is missing a date"))?;
+    let date = Month::try_from(date.month)
+        .and_then(|month| Date::from_calendar_date(date.year.into(), month, date.day))
+        .map_err(D::Error::custom)?;
+    let time = match datetime.time {
+        Some(time) => Time::from_hms_nano(
+            time.hour,
+            time.minute,
+            time.second.unwrap_or(0),
+            time.nanosecond.unwrap_or(0),
+        )
+        .map_err(D::Error::custom)?,
+        None => Time::MIDNIGHT,
+    };
+    let offset = match datetime.offset {
+        Some(Offset::Custom { minutes }) => {
+            UtcOffset::from_whole_seconds(i32::from(minutes) * 60).map_err(D::Error::custom)?
+        }
+        Some(Offset::Z) | None => UtcOffset::UTC,
+    };
+
+    Ok(Some(
+        PrimitiveDateTime::new(date, time).assume_offset(offset),
+    ))
+}
+
+/// Highlights code blocks with classes rather than inline styles. The stylesheet for them is
+/// generated by assetwrap, from the themes in `assetconfig.json`, using the same class prefix.
+///
+/// Along with syntect's default syntaxes, this knows the ones in `syntaxes/`, which are combined
+/// by `build.rs`.
+static HIGHLIGHTER: Lazy<SyntectAdapter> = Lazy::new(|| {
+    SyntectAdapterBuilder::new()
+        .syntax_set(syntect::dumps::from_binary(include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/syntaxes.packdump"
+        ))))
+        .css_with_class_prefix("hl-")
+        .build()
+});
+
+/// Other names for languages in code fences, from `syntaxes/aliases.toml`.
+static SYNTAX_ALIASES: Lazy<HashMap<String, String>> = Lazy::new(|| {
+    toml::from_str(include_str!("../syntaxes/aliases.toml"))
+        .expect("failed to parse syntaxes/aliases.toml")
+});
+
+/// Icons for alerts and `:::aside`, from the same set as link scheme icons.
+static ICONS: Lazy<HashMap<&'static str, String>> = Lazy::new(|| {
+    ["note", "tip", "warning", "aside"]
+        .into_iter()
+        .map(|name| {
+            let icon = link_schemes::icon(name).unwrap_or_else(|| panic!("missing icon `{name}`"));
+            (name, icon)
+        })
+        .collect()
+});
+
+/// A `:::details` or `:::aside` container, with the text after its name.
+#[derive(Clone)]
+struct Container {
+    details: bool,
+    title: Option<String>,
+}
+
+/// Turns `:::details`/`:::aside` containers into comrak's `>>>` multiline block quotes, keeping
+/// every line in place so positions in the result are still right. Returns the containers by the
+/// line they start on, as the block quotes don't keep what kind they were.
+///
+/// Like code fences, nested containers need a longer fence on the outside.
+fn parse_containers(body: &str) -> (String, HashMap<usize, Container>) {
+    let mut output = String::with_capacity(body.len());
+    let mut containers = HashMap::new();
+    let mut open = Vec::new();
+    let mut code_fence = None;
+
+    for (i, line) in body.lines().enumerate() {
+        let trimmed = line.trim_start();
+        let indent = &line[..line.len() - trimmed.len()];
+
+        let fence = trimmed.chars().next().filter(|c| *c == '`' || *c == '~');
+        let fence_length = fence.map_or(0, |c| trimmed.len() - trimmed.trim_start_matches(c).len());
+        match code_fence {
+            Some((c, length))
+                if fence == Some(c)
+                    && fence_length >= length
+                    && trimmed[fence_length..].trim().is_empty() =>
+            {
+                code_fence = None;
+            }
+            Some(_) => {}
+            None if fence_length >= 3 => code_fence = Some((fence.unwrap(), fence_length)),
+            None => {}
+        }
+
+        let colons = trimmed.len() - trimmed.trim_start_matches(':').len();
+        let rest = trimmed[colons..].trim();
+        let (name, title) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let quote = ">".repeat(colons);
+        if code_fence.is_some() || colons < 3 {
+            output.push_str(line);
+        } else if name == "details" || name == "aside" {
+            open.push(colons);
+            containers.insert(
+                i + 1,
+                Container {
+                    details: name == "details",
+                    title: Some(title.trim())
+                        .filter(|title| !title.is_empty())
+                        .map(str::to_owned),
+                },
+            );
+            output.push_str(indent);
+            output.push_str(&quote);
+        } else if rest.is_empty() && open.last().is_some_and(|&length| colons >= length) {
+            open.pop();
+            output.push_str(indent);
+            output.push_str(&quote);
+        } else {
+            output.push_str(line);
+        }
+        output.push('\n');
+    }
+
+    (output, containers)
+}
+
+// Alerts and containers are rendered here rather than by comrak, to give them icons and use
+// `<details>` and `<aside>`. Block quotes that didn't come from a container are left alone.
+create_formatter!(ContentFormatter<HashMap<usize, Container>>, {
+    NodeValue::Alert(ref alert) => |context, entering| {
+        context.cr()?;
+        if entering {
+            let (class, icon) = match alert.alert_type {
+                AlertType::Note => ("note", "note"),
+                AlertType::Tip => ("tip", "tip"),
+                AlertType::Important => ("important", "note"),
+                AlertType::Warning => ("warning", "warning"),
+                AlertType::Caution => ("caution", "warning"),
+            };
+            let title = alert.title.as_deref().unwrap_or(alert.alert_type.default_title());
+            let title = html! { p.alert-title { (PreEscaped(&ICONS[icon])) " " (title) } };
+            writeln!(context, "<aside class=\"alert alert-{class}\">\n{}", title.0)?;
+        } else {
+            writeln!(context, "</aside>")?;
+        }
+    },
+    NodeValue::MultilineBlockQuote(_) => |context, node, entering| {
+        let line = node.data().sourcepos.start.line;
+        let Some(container) = context.user.get(&line).cloned() else {
+            return format_node_default(context, node, entering);
+        };
+        context.cr()?;
+        match (entering, container.details) {
+            (true, true) => {
+                let title = container.title.as_deref().unwrap_or("Details");
+                writeln!(context, "<details class=\"collapsible\">\n{}", html! { summary { (title) } }.0)?;
+            }
+            (true, false) => {
+                let title = container.title.as_deref().unwrap_or("Aside");
+                let title = html! { p.sidenote-title { (PreEscaped(&ICONS["aside"])) " " (title) } };
+                writeln!(context, "<aside class=\"sidenote\">\n{}", title.0)?;
+            }
+            (false, true) => writeln!(context, "</details>")?,
+            (false, false) => writeln!(context, "</aside>")?,
+        }
+    },
+});
+
+/// The output of [`render_markdown`].
+pub struct Rendered {
+    pub metadata: Metadata,
+    pub html: String,
+    /// Number of words of prose, not counting code blocks.
+    pub word_count: usize,
+    /// Problems that didn't stop the file from rendering, such as math shown as its source.
+    pub warnings: Vec<ContentError>,
+}
+
+/// Renders a markdown file with `+++` TOML frontmatter. `file` is only used to report errors.
+/// Every problem in the file is reported, not just the first.
+pub fn render_markdown(file: &str, markdown: &str) -> Result<Rendered, ContentErrors> {
+    const DELIMITER: &str = "+++";
+
+    let Some(frontmatter_start) = markdown.lines().position(|line| line.trim() == DELIMITER) else {
+        return Err(ContentErrorKind::MissingFrontmatter.at(file, 1).into());
+    };
+    let (frontmatter, body) = Extractor::new(Splitter::EnclosingLines(DELIMITER)).extract(markdown);
+    // number of lines before the body, so positions in it can be reported as lines in the file
+    let body_offset = markdown
+        .lines()
+        .enumerate()
+        .skip(frontmatter_start + 1)
+        .find(|(_, line)| line.trim() == DELIMITER)
+        .map_or(0, |(i, _)| i + 1);
+
+    let mut errors = vec![];
+    // the body is still checked if the frontmatter is broken, so everything is reported at once
+    let metadata = toml::from_str::<Metadata>(&frontmatter)
+        .map_err(|e| {
+            // errors without a position, such as missing fields, are reported on the opening `+++`
+            let line = e.span().map_or(frontmatter_start + 1, |span| {
+                frontmatter_start + 2 + frontmatter[..span.start].matches('\n').count()
+            });
+            errors.push(ContentErrorKind::Frontmatter(e.message().to_owned()).at(file, line));
+        })
+        .ok();
 
     let mut options = comrak::Options::default();
     options.extension.autolink = true;
@@ -39,12 +318,37 @@ pub fn render_markdown(markdown: &str) -> (Metadata, String) {
     options.extension.superscript = true;
     options.extension.strikethrough = true;
     options.extension.footnotes = true;
+    options.extension.math_dollars = true;
+    options.extension.alerts = true;
+    options.extension.multiline_block_quotes = true;
     options.render.hardbreaks = true;
     options.render.r#unsafe = true;
     options.extension.header_id_prefix = Some("".to_owned());
 
     let arena = Arena::new();
-    let root = parse_document(&arena, body, &options);
+    let (body, containers) = parse_containers(body);
+    let root = parse_document(&arena, &body, &options);
+
+    if metadata.as_ref().is_some_and(|metadata| metadata.toc) {
+        let toc = arena.alloc(AstNode::new(RefCell::new(Ast::new(
+            NodeValue::HtmlBlock(NodeHtmlBlock {
+                block_type: 6,
+                literal: "<toc></toc>\n".to_owned(),
+            }),
+            LineColumn { line: 1, column: 1 },
+        ))));
+        root.prepend(toc);
+    }
+
+    // code blocks keep their contents in the block itself, so only prose is counted here
+    let word_count = root
+        .descendants()
+        .map(|node| match &node.data.borrow().value {
+            NodeValue::Text(text) => text.split_whitespace().count(),
+            NodeValue::Code(code) => code.literal.split_whitespace().count(),
+            _ => 0,
+        })
+        .sum();
 
     fn iter_nodes<'a, F>(node: &'a AstNode<'a>, f: &F)
     where
@@ -56,55 +360,123 @@ pub fn render_markdown(markdown: &str) -> (Metadata, String) {
         }
     }
 
+    let errors = RefCell::new(errors);
+    let report = |kind: ContentErrorKind, line: usize| {
+        errors.borrow_mut().push(kind.at(file, body_offset + line));
+    };
+    let warnings = RefCell::new(vec![]);
+    let warn = |kind: ContentErrorKind, line: usize| {
+        warnings
+            .borrow_mut()
+            .push(kind.at(file, body_offset + line));
+    };
     iter_nodes(root, &|node| {
         let mut data = node.data.borrow_mut();
-        if let NodeValue::Link(ref mut link) = data.value {
-            let url = &link.url;
-            if let Ok(url) = Url::parse(url) {
-                let service = url.scheme();
-                let target_url = match service {
-                    "modrinth" => {
-                        format!(
-                            "https://modrinth.com/{}{}",
-                            url.host_str().unwrap_or("mod"),
-                            url.path()
-                        )
+        let start = data.sourcepos.start;
+        match data.value {
+            NodeValue::Math(ref math) => {
+                let html = math::render(&math.literal, math.display_math).unwrap_or_else(|e| {
+                    warn(ContentErrorKind::Math(e), start.line);
+                    math::source(&math.literal, math.display_math)
+                });
+                data.value = NodeValue::HtmlInline(html);
+            }
+            NodeValue::Link(ref mut link) => {
+                if let Some((url, icon)) = link_schemes::expand(&link.url) {
+                    link.url = url;
+                    if let Some(icon) = icon {
+                        let icon = arena.alloc(AstNode::new(RefCell::new(Ast::new(
+                            NodeValue::HtmlInline(format!("{icon} ")),
+                            start,
+                        ))));
+                        node.prepend(icon);
                     }
-                    "github" => {
-                        format!(
-                            "https://github.com/{}{}",
-                            url.host_str().unwrap_or("ashhhleyyy"),
-                            url.path()
-                        )
+                }
+            }
+            NodeValue::CodeBlock(ref mut block) if block.fenced => {
+                let lang = block.info.split_whitespace().next().unwrap_or_default();
+                if let Some(alias) = SYNTAX_ALIASES.get(&lang.to_ascii_lowercase()) {
+                    block.info = block.info.replacen(lang, alias, 1);
+                } else if lang == "dot" || lang == "diagram" {
+                    match diagram::render(&block.literal) {
+                        Ok(svg) => {
+                            data.value = NodeValue::HtmlBlock(NodeHtmlBlock {
+                                block_type: 6,
+                                literal: format!("<figure class=\"diagram\">{svg}</figure>\n"),
+                            })
+                        }
+                        Err(e) => report(ContentErrorKind::Diagram(e), start.line),
                     }
-                    _ => return,
-                };
-                link.url = target_url;
-                let insert = format!("!--icon({service})--! ");
-                let new_node = arena.alloc(AstNode::new(RefCell::new(Ast::new(
-                    NodeValue::Text(insert.into()),
-                    data.sourcepos.start,
-                ))));
-                node.prepend(new_node);
+                }
             }
+            _ => {}
         }
     });
+    let mut errors = errors.into_inner();
+    let warnings = warnings.into_inner();
+    let Some(metadata) = metadata.filter(|_| errors.is_empty()) else {
+        errors.extend(warnings);
+        errors.sort_by_key(|e| e.line);
+        return Err(ContentErrors(errors));
+    };
 
     let mut html = String::new();
     let mut plugins = comrak::options::Plugins::default();
-    let adapter = SyntectAdapter::new(Some("base16-ocean.dark"));
-    plugins.render.codefence_syntax_highlighter = Some(&adapter);
-    format_html_with_plugins(root, &options, &mut html, &plugins).unwrap();
+    plugins.render.codefence_syntax_highlighter = Some(&*HIGHLIGHTER);
+    ContentFormatter::format_document_with_plugins(root, &options, &mut html, &plugins, containers)
+        .unwrap();
 
-    (metadata, replace_icons(html))
+    Ok(Rendered {
+        metadata,
+        html,
+        word_count,
+        warnings,
+    })
 }
 
-fn replace_icons(html: String) -> String {
-    ICON_REGEX.replace_all(&html, |captures: &Captures| {
-        match &captures[1] {
-            "modrinth" => r#"<svg xmlns="http://www.w3.org/2000/svg" aria-hidden="true" class="icon hover-modrinth" viewBox="0 0 512 514"><path fill-rule="evenodd" clip-rule="evenodd" d="M503.16 323.56C514.55 281.47 515.32 235.91 503.2 190.76C466.57 54.2299 326.04 -26.8001 189.33 9.77991C83.8101 38.0199 11.3899 128.07 0.689941 230.47H43.99C54.29 147.33 113.74 74.7298 199.75 51.7098C306.05 23.2598 415.13 80.6699 453.17 181.38L411.03 192.65C391.64 145.8 352.57 111.45 306.3 96.8198L298.56 140.66C335.09 154.13 364.72 184.5 375.56 224.91C391.36 283.8 361.94 344.14 308.56 369.17L320.09 412.16C390.25 383.21 432.4 310.3 422.43 235.14L464.41 223.91C468.91 252.62 467.35 281.16 460.55 308.07L503.16 323.56Z"></path><path d="M321.99 504.22C185.27 540.8 44.7501 459.77 8.11011 323.24C3.84011 307.31 1.17 291.33 0 275.46H43.27C44.36 287.37 46.4699 299.35 49.6799 311.29C53.0399 323.8 57.45 335.75 62.79 347.07L101.38 323.92C98.1299 316.42 95.39 308.6 93.21 300.47C69.17 210.87 122.41 118.77 212.13 94.7601C229.13 90.2101 246.23 88.4401 262.93 89.1501L255.19 133C244.73 133.05 234.11 134.42 223.53 137.25C157.31 154.98 118.01 222.95 135.75 289.09C136.85 293.16 138.13 297.13 139.59 300.99L188.94 271.38L174.07 231.95L220.67 184.08L279.57 171.39L296.62 192.38L269.47 219.88L245.79 227.33L228.87 244.72L237.16 267.79C237.16 267.79 253.95 285.63 253.98 285.64L277.7 279.33L294.58 260.79L331.44 249.12L342.42 273.82L304.39 320.45L240.66 340.63L212.08 308.81L162.26 338.7C187.8 367.78 226.2 383.93 266.01 380.56L277.54 423.55C218.13 431.41 160.1 406.82 124.05 361.64L85.6399 384.68C136.25 451.17 223.84 484.11 309.61 461.16C371.35 444.64 419.4 402.56 445.42 349.38L488.06 364.88C457.17 431.16 398.22 483.82 321.99 504.22Z"></path></svg>"#,
-            "github" => r#"<svg xmlns="http://www.w3.org/2000/svg" aria-hidden="true" class="icon hover-github" preserveAspectRatio="xMidYMid meet" viewBox="0 0 24 24"><path d="M12 2A10 10 0 0 0 2 12c0 4.42 2.87 8.17 6.84 9.5c.5.08.66-.23.66-.5v-1.69c-2.77.6-3.36-1.34-3.36-1.34c-.46-1.16-1.11-1.47-1.11-1.47c-.91-.62.07-.6.07-.6c1 .07 1.53 1.03 1.53 1.03c.87 1.52 2.34 1.07 2.91.83c.09-.65.35-1.09.63-1.34c-2.22-.25-4.55-1.11-4.55-4.92c0-1.11.38-2 1.03-2.71c-.1-.25-.45-1.29.1-2.64c0 0 .84-.27 2.75 1.02c.79-.22 1.65-.33 2.5-.33c.85 0 1.71.11 2.5.33c1.91-1.29 2.75-1.02 2.75-1.02c.55 1.35.2 2.39.1 2.64c.65.71 1.03 1.6 1.03 2.71c0 3.82-2.34 4.66-4.57 4.91c.36.31.69.92.69 1.85V21c0 .27.16.59.67.5C19.14 20.16 22 16.42 22 12A10 10 0 0 0 12 2Z"></path></svg>"#,
-            _ => "",
-        }
-    }).to_string()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_problem_is_reported() {
+        let markdown = "+++\ntitle = 1\n+++\n\nSome text.\n\n```dot\ndigraph {\n```\n\n```dot\nnot a graph\n```\n";
+        let errors = render_markdown("blog/test.md", markdown)
+            .err()
+            .expect("should fail")
+            .0;
+        let lines = errors.iter().map(|e| e.line).collect::<Vec<_>>();
+        assert_eq!(lines, [2, 7, 11]);
+        assert!(matches!(errors[0].kind, ContentErrorKind::Frontmatter(_)));
+        assert!(matches!(errors[1].kind, ContentErrorKind::Diagram(_)));
+        assert!(matches!(errors[2].kind, ContentErrorKind::Diagram(_)));
+    }
+
+    #[test]
+    fn valid_file_renders() {
+        let markdown = "+++\ntitle = \"Test\"\ndescription = \"A test\"\n+++\n\nHello *world*\n";
+        let rendered =
+            render_markdown("blog/test.md", markdown).unwrap_or_else(|e| panic!("{:?}", e.0));
+        assert_eq!(rendered.metadata.title, "Test");
+        assert!(rendered.html.contains("<em>world</em>"));
+        assert_eq!(rendered.word_count, 2);
+    }
+
+    #[test]
+    fn unsupported_math_is_shown_as_source() {
+        let markdown =
+            "+++\ntitle = \"Test\"\ndescription = \"A test\"\n+++\n\n$x < \\nope$ and $y$\n";
+        let rendered =
+            render_markdown("blog/test.md", markdown).unwrap_or_else(|e| panic!("{:?}", e.0));
+        assert!(rendered
+            .html
+            .contains(r#"<code class="math-source">$x &lt; \nope$</code>"#));
+        assert!(rendered.html.contains(r#"<math alttext="y">"#));
+        assert_eq!(rendered.warnings.len(), 1);
+        assert_eq!(rendered.warnings[0].line, 6);
+        assert!(matches!(
+            rendered.warnings[0].kind,
+            ContentErrorKind::Math(_)
+        ));
+    }
 }
@@ -0,0 +1,981 @@
+use maud::{html, Markup, Render};
+
+/// Renders TeX math from markdown to MathML, with a plain-text version in `alttext` for places
+/// that can't show MathML, such as most feed readers.
+///
+/// Only a subset of TeX is supported: the usual symbols and functions, fractions, roots, scripts,
+/// accents, `\left`/`\right`, text, and matrix-like environments.
+pub fn render(tex: &str, display: bool) -> Result<String, String> {
+    let mut parser = Parser { tex, pos: 0 };
+    let (nodes, stop) = parser.parse_row()?;
+    match stop {
+        Stop::Eof => {}
+        Stop::Brace => return Err("unexpected `}`".to_owned()),
+        Stop::Cell => return Err("`&` outside of an environment".to_owned()),
+        Stop::Row => return Err("`\\\\` outside of an environment".to_owned()),
+        Stop::End(env) => return Err(format!("`\\end{{{env}}}` without `\\begin{{{env}}}`")),
+        Stop::Right => return Err("`\\right` without `\\left`".to_owned()),
+    }
+
+    let root = Node::Row(nodes);
+    let mut text = String::new();
+    root.text(&mut text);
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let markup = html! {
+        math display=[display.then_some("block")] alttext=(text) { (root) }
+    };
+    Ok(markup.into_string())
+}
+
+/// Shows math that couldn't be rendered as its TeX source, so the rest of the page still works.
+pub fn source(tex: &str, display: bool) -> String {
+    let delimiter = if display { "$$" } else { "$" };
+    html! { code.math-source { (delimiter) (tex) (delimiter) } }.into_string()
+}
+
+enum Node {
+    Ident {
+        text: String,
+        normal: bool,
+    },
+    Number(String),
+    Op(String),
+    /// Operators like `\sum` and `\lim`, which can have limits above and below them.
+    BigOp {
+        op: &'static str,
+        large: bool,
+        limits: bool,
+    },
+    Text(String),
+    Space(&'static str),
+    Row(Vec<Node>),
+    Frac {
+        num: Box<Node>,
+        den: Box<Node>,
+        line: bool,
+    },
+    Root {
+        radicand: Box<Node>,
+        index: Option<Box<Node>>,
+    },
+    Scripts {
+        base: Box<Node>,
+        sub: Option<Box<Node>>,
+        sup: Option<Box<Node>>,
+    },
+    Accent {
+        base: Box<Node>,
+        accent: &'static str,
+        combining: char,
+    },
+    Table {
+        rows: Vec<Vec<Node>>,
+        env: Env,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum Env {
+    Matrix(&'static str, &'static str),
+    Cases,
+    Aligned,
+}
+
+impl Env {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "matrix" => Self::Matrix("", ""),
+            "pmatrix" => Self::Matrix("(", ")"),
+            "bmatrix" => Self::Matrix("[", "]"),
+            "Bmatrix" => Self::Matrix("{", "}"),
+            "vmatrix" => Self::Matrix("|", "|"),
+            "Vmatrix" => Self::Matrix("‖", "‖"),
+            "cases" => Self::Cases,
+            "aligned" | "align" | "align*" => Self::Aligned,
+            _ => return None,
+        })
+    }
+
+    fn delimiters(self) -> (&'static str, &'static str) {
+        match self {
+            Self::Matrix(open, close) => (open, close),
+            Self::Cases => ("{", ""),
+            Self::Aligned => ("", ""),
+        }
+    }
+}
+
+/// What ended a row of nodes.
+enum Stop {
+    Eof,
+    Brace,
+    Cell,
+    Row,
+    End(String),
+    Right,
+}
+
+struct Parser<'a> {
+    tex: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.tex[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.next();
+        }
+    }
+
+    fn parse_row(&mut self) -> Result<(Vec<Node>, Stop), String> {
+        let mut nodes = vec![];
+        loop {
+            self.skip_whitespace();
+            let Some(c) = self.peek() else {
+                return Ok((nodes, Stop::Eof));
+            };
+            let rest = &self.tex[self.pos..];
+            match c {
+                '}' => {
+                    self.next();
+                    return Ok((nodes, Stop::Brace));
+                }
+                '&' => {
+                    self.next();
+                    return Ok((nodes, Stop::Cell));
+                }
+                '_' | '^' | '\'' => {
+                    // scripts at the start of a group apply to nothing, like in TeX
+                    let base = nodes.pop().unwrap_or(Node::Row(vec![]));
+                    nodes.push(self.parse_scripts(base)?);
+                }
+                '\\' if rest.starts_with("\\\\") => {
+                    self.pos += 2;
+                    return Ok((nodes, Stop::Row));
+                }
+                '\\' if command_name(rest) == "\\end" => {
+                    self.pos += "\\end".len();
+                    let env = self.parse_raw_group()?;
+                    return Ok((nodes, Stop::End(env)));
+                }
+                '\\' if command_name(rest) == "\\right" => {
+                    self.pos += "\\right".len();
+                    return Ok((nodes, Stop::Right));
+                }
+                _ => nodes.push(self.parse_atom()?),
+            }
+        }
+    }
+
+    /// Parses the contents of a `{...}` group, after its opening brace.
+    fn parse_group(&mut self) -> Result<Node, String> {
+        match self.parse_row()? {
+            (nodes, Stop::Brace) => Ok(Node::Row(nodes)),
+            _ => Err("missing `}`".to_owned()),
+        }
+    }
+
+    /// Parses a required argument to a command, which is either a group or a single token.
+    fn parse_argument(&mut self) -> Result<Node, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => {
+                self.next();
+                self.parse_group()
+            }
+            // `x^10` is `x` squared and then a 0
+            Some(c) if c.is_ascii_digit() => {
+                self.next();
+                Ok(Node::Number(c.to_string()))
+            }
+            Some('}' | '&' | '_' | '^') | None => Err("missing argument".to_owned()),
+            Some(_) => self.parse_atom(),
+        }
+    }
+
+    /// Reads a `{...}` argument without parsing it, for text and environment names.
+    fn parse_raw_group(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        if self.next() != Some('{') {
+            return Err("expected `{`".to_owned());
+        }
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(c) = self.next() {
+            match c {
+                '\\' => {
+                    self.next();
+                }
+                '{' => depth += 1,
+                '}' if depth == 0 => return Ok(self.tex[start..self.pos - 1].to_owned()),
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+        Err("missing `}`".to_owned())
+    }
+
+    fn parse_scripts(&mut self, base: Node) -> Result<Node, String> {
+        let (base, mut sub, mut sup) = match base {
+            Node::Scripts { base, sub, sup } => (base, sub, sup),
+            base => (Box::new(base), None, None),
+        };
+        match self.next() {
+            Some('_') if sub.is_some() => return Err("double subscript".to_owned()),
+            Some('_') => sub = Some(Box::new(self.parse_argument()?)),
+            Some('^') if sup.is_some() => return Err("double superscript".to_owned()),
+            Some('^') => sup = Some(Box::new(self.parse_argument()?)),
+            _ => {
+                let mut primes = "′".to_owned();
+                while self.peek() == Some('\'') {
+                    self.next();
+                    primes.push('′');
+                }
+                let primes = Node::Op(primes);
+                sup = Some(Box::new(match sup {
+                    Some(sup) => Node::Row(vec![primes, *sup]),
+                    None => primes,
+                }));
+            }
+        }
+        Ok(Node::Scripts { base, sub, sup })
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, String> {
+        let c = self.next().ok_or("unexpected end of math")?;
+        Ok(match c {
+            '{' => self.parse_group()?,
+            '\\' => self.parse_command()?,
+            c if c.is_ascii_digit() => {
+                let start = self.pos - 1;
+                while let Some(c) = self.peek() {
+                    let decimal_point = c == '.'
+                        && self.tex[self.pos + 1..].starts_with(|c: char| c.is_ascii_digit());
+                    if !c.is_ascii_digit() && !decimal_point {
+                        break;
+                    }
+                    self.next();
+                }
+                Node::Number(self.tex[start..self.pos].to_owned())
+            }
+            c if c.is_alphabetic() => Node::Ident {
+                text: c.to_string(),
+                normal: false,
+            },
+            '-' => Node::Op("−".to_owned()),
+            '*' => Node::Op("∗".to_owned()),
+            '~' => Node::Space("0.25em"),
+            '+' | '=' | '<' | '>' | ',' | ';' | ':' | '!' | '?' | '(' | ')' | '[' | ']' | '|'
+            | '/' | '.' => Node::Op(c.to_string()),
+            c => return Err(format!("unexpected `{c}`")),
+        })
+    }
+
+    fn parse_command(&mut self) -> Result<Node, String> {
+        // the backslash has already been read
+        let name = &command_name(&self.tex[self.pos - 1..])[1..];
+        self.pos += name.len();
+
+        if let Some(symbol) = symbol(name) {
+            return Ok(symbol);
+        }
+        Ok(match name {
+            "frac" | "dfrac" | "tfrac" => Node::Frac {
+                num: Box::new(self.parse_argument()?),
+                den: Box::new(self.parse_argument()?),
+                line: true,
+            },
+            "binom" => Node::Row(vec![
+                Node::Op("(".to_owned()),
+                Node::Frac {
+                    num: Box::new(self.parse_argument()?),
+                    den: Box::new(self.parse_argument()?),
+                    line: false,
+                },
+                Node::Op(")".to_owned()),
+            ]),
+            "sqrt" => {
+                self.skip_whitespace();
+                let index = if self.peek() == Some('[') {
+                    self.next();
+                    let end = self.tex[self.pos..].find(']').ok_or("missing `]`")?;
+                    let index = &self.tex[self.pos..self.pos + end];
+                    self.pos += end + 1;
+                    let mut parser = Parser { tex: index, pos: 0 };
+                    match parser.parse_row()? {
+                        (nodes, Stop::Eof) => Some(Box::new(Node::Row(nodes))),
+                        _ => return Err("invalid root index".to_owned()),
+                    }
+                } else {
+                    None
+                };
+                Node::Root {
+                    radicand: Box::new(self.parse_argument()?),
+                    index,
+                }
+            }
+            "text" | "textrm" | "mbox" => Node::Text(unescape_text(&self.parse_raw_group()?)),
+            "mathrm" | "operatorname" => Node::Ident {
+                text: unescape_text(&self.parse_raw_group()?),
+                normal: true,
+            },
+            "mathit" => self.parse_argument()?,
+            "mathbf" => map_letters(self.parse_argument()?, bold),
+            "mathbb" => map_letters(self.parse_argument()?, double_struck),
+            "hat" | "widehat" => self.parse_accent("^", '\u{302}')?,
+            "bar" | "overline" => self.parse_accent("‾", '\u{305}')?,
+            "tilde" | "widetilde" => self.parse_accent("~", '\u{303}')?,
+            "vec" => self.parse_accent("→", '\u{20d7}')?,
+            "dot" => self.parse_accent("˙", '\u{307}')?,
+            "ddot" => self.parse_accent("¨", '\u{308}')?,
+            "left" => {
+                let open = self.parse_delimiter()?;
+                let (mut nodes, stop) = self.parse_row()?;
+                if !matches!(stop, Stop::Right) {
+                    return Err("missing `\\right`".to_owned());
+                }
+                let close = self.parse_delimiter()?;
+                nodes.splice(0..0, open);
+                nodes.extend(close);
+                Node::Row(nodes)
+            }
+            "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl" | "Bigr" | "biggl"
+            | "biggr" | "Biggl" | "Biggr" => {
+                Node::Row(self.parse_delimiter()?.into_iter().collect())
+            }
+            "pmod" => {
+                let modulus = self.parse_argument()?;
+                Node::Row(vec![
+                    Node::Op("(".to_owned()),
+                    Node::Op("mod".to_owned()),
+                    modulus,
+                    Node::Op(")".to_owned()),
+                ])
+            }
+            "begin" => {
+                let name = self.parse_raw_group()?;
+                let env =
+                    Env::from_name(&name).ok_or_else(|| format!("unknown environment `{name}`"))?;
+                self.parse_table(env, &name)?
+            }
+            "," | ":" | ">" | ";" | " " => Node::Space("0.25em"),
+            "!" => Node::Space("-0.15em"),
+            "quad" => Node::Space("1em"),
+            "qquad" => Node::Space("2em"),
+            "{" | "}" | "|" => Node::Op(name.to_owned()),
+            "#" | "$" | "%" | "&" | "_" => Node::Ident {
+                text: name.to_owned(),
+                normal: true,
+            },
+            _ => return Err(format!("unknown command `\\{name}`")),
+        })
+    }
+
+    fn parse_accent(&mut self, accent: &'static str, combining: char) -> Result<Node, String> {
+        Ok(Node::Accent {
+            base: Box::new(self.parse_argument()?),
+            accent,
+            combining,
+        })
+    }
+
+    /// Parses the delimiter after `\left`, `\right` or `\big`, where `.` means no delimiter.
+    fn parse_delimiter(&mut self) -> Result<Option<Node>, String> {
+        self.skip_whitespace();
+        match self.parse_atom()? {
+            Node::Op(op) if op == "." => Ok(None),
+            delimiter @ Node::Op(_) => Ok(Some(delimiter)),
+            _ => Err("expected a delimiter".to_owned()),
+        }
+    }
+
+    fn parse_table(&mut self, env: Env, name: &str) -> Result<Node, String> {
+        let mut rows = vec![];
+        let mut row = vec![];
+        loop {
+            let (nodes, stop) = self.parse_row()?;
+            row.push(Node::Row(nodes));
+            match stop {
+                Stop::Cell => {}
+                Stop::Row => rows.push(std::mem::take(&mut row)),
+                Stop::End(end) if end == name => break,
+                _ => return Err(format!("missing `\\end{{{name}}}`")),
+            }
+        }
+        // a trailing `\\` doesn't start another row
+        if !matches!(&row[..], [Node::Row(cell)] if cell.is_empty()) {
+            rows.push(row);
+        }
+        Ok(Node::Table { rows, env })
+    }
+}
+
+/// The name of the command at the start of `tex`, including its backslash.
+fn command_name(tex: &str) -> &str {
+    let Some(rest) = tex.strip_prefix('\\') else {
+        return "";
+    };
+    let letters = rest
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(rest.len());
+    let len = match letters {
+        // commands like `\,` are a single symbol
+        0 => rest.chars().next().map_or(0, char::len_utf8),
+        // `\align*` and friends
+        letters if rest[letters..].starts_with('*') => letters + 1,
+        letters => letters,
+    };
+    &tex[..1 + len]
+}
+
+fn unescape_text(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            '{' | '}' => {}
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+fn symbol(name: &str) -> Option<Node> {
+    let ident = |text: &str, normal| Node::Ident {
+        text: text.to_owned(),
+        normal,
+    };
+    let op = |text: &str| Node::Op(text.to_owned());
+    let big_op = |op, large, limits| Node::BigOp { op, large, limits };
+
+    Some(match name {
+        "alpha" => ident("α", false),
+        "beta" => ident("β", false),
+        "gamma" => ident("γ", false),
+        "delta" => ident("δ", false),
+        "epsilon" => ident("ϵ", false),
+        "varepsilon" => ident("ε", false),
+        "zeta" => ident("ζ", false),
+        "eta" => ident("η", false),
+        "theta" => ident("θ", false),
+        "vartheta" => ident("ϑ", false),
+        "iota" => ident("ι", false),
+        "kappa" => ident("κ", false),
+        "lambda" => ident("λ", false),
+        "mu" => ident("μ", false),
+        "nu" => ident("ν", false),
+        "xi" => ident("ξ", false),
+        "pi" => ident("π", false),
+        "varpi" => ident("ϖ", false),
+        "rho" => ident("ρ", false),
+        "varrho" => ident("ϱ", false),
+        "sigma" => ident("σ", false),
+        "varsigma" => ident("ς", false),
+        "tau" => ident("τ", false),
+        "upsilon" => ident("υ", false),
+        "phi" => ident("ϕ", false),
+        "varphi" => ident("φ", false),
+        "chi" => ident("χ", false),
+        "psi" => ident("ψ", false),
+        "omega" => ident("ω", false),
+        "Gamma" => ident("Γ", true),
+        "Delta" => ident("Δ", true),
+        "Theta" => ident("Θ", true),
+        "Lambda" => ident("Λ", true),
+        "Xi" => ident("Ξ", true),
+        "Pi" => ident("Π", true),
+        "Sigma" => ident("Σ", true),
+        "Upsilon" => ident("Υ", true),
+        "Phi" => ident("Φ", true),
+        "Psi" => ident("Ψ", true),
+        "Omega" => ident("Ω", true),
+        "infty" => ident("∞", true),
+        "ell" => ident("ℓ", false),
+        "hbar" => ident("ℏ", false),
+        "emptyset" | "varnothing" => ident("∅", true),
+        "partial" => ident("∂", true),
+        "nabla" => ident("∇", true),
+        "aleph" => ident("ℵ", true),
+        "top" => ident("⊤", true),
+        "bot" => ident("⊥", true),
+        "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "arcsin" | "arccos" | "arctan" | "sinh"
+        | "cosh" | "tanh" | "log" | "ln" | "lg" | "exp" | "det" | "dim" | "ker" | "deg" | "arg"
+        | "hom" | "gcd" | "Pr" => ident(name, true),
+        "lim" => big_op("lim", false, true),
+        "limsup" => big_op("lim sup", false, true),
+        "liminf" => big_op("lim inf", false, true),
+        "max" => big_op("max", false, true),
+        "min" => big_op("min", false, true),
+        "sup" => big_op("sup", false, true),
+        "inf" => big_op("inf", false, true),
+        "sum" => big_op("∑", true, true),
+        "prod" => big_op("∏", true, true),
+        "coprod" => big_op("∐", true, true),
+        "bigcup" => big_op("⋃", true, true),
+        "bigcap" => big_op("⋂", true, true),
+        "bigoplus" => big_op("⨁", true, true),
+        "bigvee" => big_op("⋁", true, true),
+        "bigwedge" => big_op("⋀", true, true),
+        "int" => big_op("∫", true, false),
+        "iint" => big_op("∬", true, false),
+        "oint" => big_op("∮", true, false),
+        "times" => op("×"),
+        "cdot" => op("⋅"),
+        "div" => op("÷"),
+        "pm" => op("±"),
+        "mp" => op("∓"),
+        "ast" => op("∗"),
+        "star" => op("⋆"),
+        "circ" => op("∘"),
+        "bullet" => op("∙"),
+        "oplus" => op("⊕"),
+        "otimes" => op("⊗"),
+        "cup" => op("∪"),
+        "cap" => op("∩"),
+        "setminus" | "backslash" => op("∖"),
+        "land" | "wedge" => op("∧"),
+        "lor" | "vee" => op("∨"),
+        "neg" | "lnot" => op("¬"),
+        "leq" | "le" => op("≤"),
+        "geq" | "ge" => op("≥"),
+        "neq" | "ne" => op("≠"),
+        "approx" => op("≈"),
+        "equiv" => op("≡"),
+        "sim" => op("∼"),
+        "simeq" => op("≃"),
+        "cong" => op("≅"),
+        "propto" => op("∝"),
+        "ll" => op("≪"),
+        "gg" => op("≫"),
+        "in" => op("∈"),
+        "notin" => op("∉"),
+        "ni" => op("∋"),
+        "subset" => op("⊂"),
+        "subseteq" => op("⊆"),
+        "supset" => op("⊃"),
+        "supseteq" => op("⊇"),
+        "mid" => op("∣"),
+        "parallel" => op("∥"),
+        "perp" => op("⊥"),
+        "to" | "rightarrow" => op("→"),
+        "leftarrow" | "gets" => op("←"),
+        "leftrightarrow" => op("↔"),
+        "Rightarrow" => op("⇒"),
+        "Leftarrow" => op("⇐"),
+        "Leftrightarrow" => op("⇔"),
+        "implies" => op("⟹"),
+        "iff" => op("⟺"),
+        "mapsto" => op("↦"),
+        "uparrow" => op("↑"),
+        "downarrow" => op("↓"),
+        "forall" => op("∀"),
+        "exists" => op("∃"),
+        "nexists" => op("∄"),
+        "ldots" | "dots" => op("…"),
+        "cdots" => op("⋯"),
+        "vdots" => op("⋮"),
+        "ddots" => op("⋱"),
+        "colon" => op(":"),
+        "bmod" | "mod" => op("mod"),
+        "langle" => op("⟨"),
+        "rangle" => op("⟩"),
+        "lfloor" => op("⌊"),
+        "rfloor" => op("⌋"),
+        "lceil" => op("⌈"),
+        "rceil" => op("⌉"),
+        "vert" | "lvert" | "rvert" => op("|"),
+        "Vert" | "lVert" | "rVert" => op("‖"),
+        "prime" => op("′"),
+        _ => return None,
+    })
+}
+
+fn map_letters(node: Node, map: fn(char) -> Option<char>) -> Node {
+    match node {
+        Node::Ident { text, normal } => Node::Ident {
+            text: text.chars().map(|c| map(c).unwrap_or(c)).collect(),
+            normal,
+        },
+        Node::Number(number) => Node::Ident {
+            text: number.chars().map(|c| map(c).unwrap_or(c)).collect(),
+            normal: true,
+        },
+        Node::Row(nodes) => Node::Row(
+            nodes
+                .into_iter()
+                .map(|node| map_letters(node, map))
+                .collect(),
+        ),
+        node => node,
+    }
+}
+
+fn offset(c: char, start: char, base: u32) -> Option<char> {
+    char::from_u32(base + (c as u32 - start as u32))
+}
+
+fn bold(c: char) -> Option<char> {
+    match c {
+        'A'..='Z' => offset(c, 'A', 0x1d400),
+        'a'..='z' => offset(c, 'a', 0x1d41a),
+        '0'..='9' => offset(c, '0', 0x1d7ce),
+        _ => None,
+    }
+}
+
+fn double_struck(c: char) -> Option<char> {
+    match c {
+        // these letters were in Unicode before the rest of the alphabet
+        'C' => Some('ℂ'),
+        'H' => Some('ℍ'),
+        'N' => Some('ℕ'),
+        'P' => Some('ℙ'),
+        'Q' => Some('ℚ'),
+        'R' => Some('ℝ'),
+        'Z' => Some('ℤ'),
+        'A'..='Z' => offset(c, 'A', 0x1d538),
+        'a'..='z' => offset(c, 'a', 0x1d552),
+        '0'..='9' => offset(c, '0', 0x1d7d8),
+        _ => None,
+    }
+}
+
+fn superscript(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '⁰',
+        '1' => '¹',
+        '2' => '²',
+        '3' => '³',
+        '4' => '⁴',
+        '5' => '⁵',
+        '6' => '⁶',
+        '7' => '⁷',
+        '8' => '⁸',
+        '9' => '⁹',
+        '+' => '⁺',
+        '−' => '⁻',
+        'n' => 'ⁿ',
+        'i' => 'ⁱ',
+        '′' => '′',
+        _ => return None,
+    })
+}
+
+fn subscript(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '₀',
+        '1' => '₁',
+        '2' => '₂',
+        '3' => '₃',
+        '4' => '₄',
+        '5' => '₅',
+        '6' => '₆',
+        '7' => '₇',
+        '8' => '₈',
+        '9' => '₉',
+        '+' => '₊',
+        '−' => '₋',
+        _ => return None,
+    })
+}
+
+impl Node {
+    fn has_limits(&self) -> bool {
+        matches!(self, Self::BigOp { limits: true, .. })
+    }
+
+    /// Writes the node as plain text, using Unicode for symbols and simple scripts.
+    fn text(&self, out: &mut String) {
+        match self {
+            Self::Ident { text, .. } | Self::Number(text) | Self::Text(text) => out.push_str(text),
+            Self::Op(op) => match op.as_str() {
+                "," | ";" => out.push_str(&format!("{op} ")),
+                "(" | ")" | "[" | "]" | "{" | "}" | "|" | "‖" | "⟨" | "⟩" | "⌊" | "⌋" | "⌈"
+                | "⌉" | "!" | "." | "′" | "/" => out.push_str(op),
+                // a sign rather than an operation
+                "−" | "+" | "±" if out.trim_end().is_empty() || out.ends_with(['(', ' ']) => {
+                    out.push_str(op)
+                }
+                op => out.push_str(&format!(" {op} ")),
+            },
+            Self::BigOp { op, .. } => out.push_str(op),
+            Self::Space(_) => out.push(' '),
+            Self::Row(nodes) => nodes.iter().for_each(|node| node.text(out)),
+            Self::Frac { num, den, .. } => {
+                out.push_str(&format!("{}/{}", num.grouped_text(), den.grouped_text()))
+            }
+            Self::Root { radicand, index } => {
+                if let Some(index) = index {
+                    out.push_str(&script_text(&index.plain_text(), superscript, '^'));
+                }
+                out.push('√');
+                out.push_str(&radicand.grouped_text());
+            }
+            Self::Scripts { base, sub, sup } => {
+                base.text(out);
+                if let Some(sub) = sub {
+                    out.push_str(&script_text(&sub.plain_text(), subscript, '_'));
+                }
+                if let Some(sup) = sup {
+                    out.push_str(&script_text(&sup.plain_text(), superscript, '^'));
+                }
+                if let Self::BigOp { .. } = **base {
+                    out.push(' ');
+                }
+            }
+            Self::Accent {
+                base, combining, ..
+            } => {
+                let base = base.plain_text();
+                if base.chars().count() == 1 {
+                    out.push_str(&base);
+                    out.push(*combining);
+                } else {
+                    out.push_str(&format!("({base}){combining}"));
+                }
+            }
+            Self::Table { rows, env } => {
+                let (open, close) = env.delimiters();
+                let separator = match env {
+                    Env::Aligned => "",
+                    _ => ", ",
+                };
+                let rows = rows
+                    .iter()
+                    .map(|row| {
+                        row.iter()
+                            .map(Node::plain_text)
+                            .collect::<Vec<_>>()
+                            .join(separator)
+                    })
+                    .collect::<Vec<_>>();
+                out.push_str(&format!("{open}{}{close}", rows.join("; ")));
+            }
+        }
+    }
+
+    fn plain_text(&self) -> String {
+        let mut text = String::new();
+        self.text(&mut text);
+        text.trim().to_owned()
+    }
+
+    /// Plain text, in brackets unless it's a single number or identifier.
+    fn grouped_text(&self) -> String {
+        let text = self.plain_text();
+        if text.chars().all(|c| c.is_alphanumeric() || c == '.') {
+            text
+        } else {
+            format!("({text})")
+        }
+    }
+}
+
+/// Writes a subscript or superscript using Unicode characters if possible, or `marker` otherwise.
+fn script_text(text: &str, map: fn(char) -> Option<char>, marker: char) -> String {
+    if let Some(mapped) = text.chars().map(map).collect::<Option<String>>() {
+        mapped
+    } else if text.chars().count() == 1 {
+        format!("{marker}{text}")
+    } else {
+        format!("{marker}({text})")
+    }
+}
+
+impl Render for Node {
+    fn render(&self) -> Markup {
+        match self {
+            Self::Ident { text, normal } => html! {
+                mi mathvariant=[normal.then_some("normal")] { (text) }
+            },
+            Self::Number(number) => html! { mn { (number) } },
+            Self::Op(op) => html! { mo { (op) } },
+            Self::BigOp { op, large, limits } => html! {
+                mo largeop=[large.then_some("true")] movablelimits=(limits) { (op) }
+            },
+            Self::Text(text) => html! { mtext { (text) } },
+            // MathML elements need closing tags, even when empty, as HTML only has void elements
+            Self::Space(width) => html! { mspace width=(width) {} },
+            Self::Row(nodes) => html! { mrow { @for node in nodes { (node) } } },
+            Self::Frac { num, den, line } => html! {
+                mfrac linethickness=[(!line).then_some("0")] { (num) (den) }
+            },
+            Self::Root {
+                radicand,
+                index: None,
+            } => html! { msqrt { (radicand) } },
+            Self::Root {
+                radicand,
+                index: Some(index),
+            } => html! { mroot { (radicand) (index) } },
+            Self::Scripts { base, sub, sup } => {
+                let limits = base.has_limits();
+                match (sub, sup) {
+                    (Some(sub), Some(sup)) if limits => {
+                        html! { munderover { (base) (sub) (sup) } }
+                    }
+                    (Some(sub), None) if limits => html! { munder { (base) (sub) } },
+                    (None, Some(sup)) if limits => html! { mover { (base) (sup) } },
+                    (Some(sub), Some(sup)) => html! { msubsup { (base) (sub) (sup) } },
+                    (Some(sub), None) => html! { msub { (base) (sub) } },
+                    (None, Some(sup)) => html! { msup { (base) (sup) } },
+                    (None, None) => base.render(),
+                }
+            }
+            Self::Accent { base, accent, .. } => html! {
+                mover accent="true" { (base) mo stretchy="true" { (accent) } }
+            },
+            Self::Table { rows, env } => {
+                let (open, close) = env.delimiters();
+                let align = match env {
+                    Env::Matrix(..) => None,
+                    Env::Cases => Some("left"),
+                    Env::Aligned => Some("right left"),
+                };
+                html! {
+                    mrow {
+                        @if !open.is_empty() { mo { (open) } }
+                        mtable columnalign=[align] {
+                            @for row in rows {
+                                mtr { @for cell in row { mtd { (cell) } } }
+                            }
+                        }
+                        @if !close.is_empty() { mo { (close) } }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alttext(tex: &str) -> String {
+        let mathml = render(tex, false).unwrap();
+        let start = mathml.find("alttext=\"").unwrap() + "alttext=\"".len();
+        let end = start + mathml[start..].find('"').unwrap();
+        mathml[start..end].to_owned()
+    }
+
+    #[test]
+    fn fractions() {
+        assert_eq!(
+            render(r"\frac{1}{x}", false).unwrap(),
+            r#"<math alttext="1/x"><mrow><mfrac><mrow><mn>1</mn></mrow><mrow><mi>x</mi></mrow></mfrac></mrow></math>"#
+        );
+        assert!(render(r"\binom n k", false)
+            .unwrap()
+            .contains(r#"<mfrac linethickness="0"><mi>n</mi><mi>k</mi></mfrac>"#));
+        assert_eq!(alttext(r"\frac{a+b}{2}"), "(a + b)/2");
+    }
+
+    #[test]
+    fn scripts() {
+        assert_eq!(
+            render("x_1^2", false).unwrap(),
+            r#"<math alttext="x₁²"><mrow><msubsup><mi>x</mi><mn>1</mn><mn>2</mn></msubsup></mrow></math>"#
+        );
+        assert!(render("x^10", false)
+            .unwrap()
+            .contains("<msup><mi>x</mi><mn>1</mn></msup><mn>0</mn>"));
+        assert!(render(r"\sum_{i=0}^n i", true)
+            .unwrap()
+            .starts_with(r#"<math display="block""#));
+        assert!(render(r"\sum_{i=0}^n i", false)
+            .unwrap()
+            .contains("<munderover>"));
+        assert_eq!(alttext("x^{12}"), "x¹²");
+        assert_eq!(alttext("x^{n+1}"), "x^(n + 1)");
+        assert_eq!(alttext("x_i"), "x_i");
+        assert_eq!(alttext("f'(x)"), "f′(x)");
+        assert_eq!(render("x_1_2", false).unwrap_err(), "double subscript");
+    }
+
+    #[test]
+    fn environments() {
+        let mathml = render(r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}", true).unwrap();
+        assert!(mathml.contains("<mo>(</mo><mtable>"));
+        assert_eq!(mathml.matches("<mtr>").count(), 2);
+        assert_eq!(mathml.matches("<mtd>").count(), 4);
+        assert_eq!(
+            alttext(r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}"),
+            "(a, b; c, d)"
+        );
+
+        let mathml = render(
+            r"\begin{cases} 1 & x > 0 \\ 0 & \text{otherwise} \\ \end{cases}",
+            true,
+        )
+        .unwrap();
+        assert!(mathml.contains(r#"<mtable columnalign="left">"#));
+        assert_eq!(mathml.matches("<mtr>").count(), 2);
+        assert!(mathml.contains("<mtext>otherwise</mtext>"));
+
+        assert_eq!(
+            render(r"\begin{matrix} a", false).unwrap_err(),
+            r"missing `\end{matrix}`"
+        );
+        assert_eq!(
+            render(r"\begin{nope} a \end{nope}", false).unwrap_err(),
+            "unknown environment `nope`"
+        );
+        assert_eq!(
+            render("a & b", false).unwrap_err(),
+            "`&` outside of an environment"
+        );
+    }
+
+    #[test]
+    fn special_characters_are_escaped() {
+        let mathml = render(r"a < b \text{ & } c > d", false).unwrap();
+        assert!(mathml.contains("<mo>&lt;</mo>"));
+        assert!(mathml.contains("<mtext> &amp; </mtext>"));
+        assert!(mathml.contains(r#"alttext="a &lt; b &amp; c &gt; d""#));
+        assert!(!mathml.contains("< b"));
+    }
+
+    #[test]
+    fn alttext_is_plain_text() {
+        assert_eq!(alttext(r"\sqrt{x^2 + 1}"), "√(x² + 1)");
+        assert_eq!(alttext(r"\alpha \leq \beta"), "α ≤ β");
+        assert_eq!(alttext(r"\vec v"), "v\u{20d7}");
+        assert_eq!(alttext(r"\mathbb{R}"), "ℝ");
+        assert_eq!(alttext(r"-x"), "−x");
+    }
+
+    #[test]
+    fn unsupported_input_is_an_error() {
+        assert_eq!(
+            render(r"\unknown x", false).unwrap_err(),
+            r"unknown command `\unknown`"
+        );
+        assert_eq!(render("{x", false).unwrap_err(), "missing `}`");
+        assert_eq!(render("x}", false).unwrap_err(), "unexpected `}`");
+        assert_eq!(render(r"\left( x", false).unwrap_err(), r"missing `\right`");
+    }
+
+    #[test]
+    fn source_is_escaped() {
+        assert_eq!(
+            source(r"a < \unknown{b}", false),
+            r#"<code class="math-source">$a &lt; \unknown{b}$</code>"#
+        );
+        assert_eq!(
+            source("x & y", true),
+            r#"<code class="math-source">$$x &amp; y$$</code>"#
+        );
+    }
+}
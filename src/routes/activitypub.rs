@@ -0,0 +1,97 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Bytes,
+    extract::{Extension, Path, Query},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+use reqwest::header::CONTENT_TYPE;
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::feeds::PageQuery;
+use crate::{
+    activitypub::{self, ActivityPub, ACCOUNT, ACTIVITY_JSON, ACTOR_ID},
+    content::ContentIndex,
+    error::WebsiteError,
+};
+
+fn json_response(content_type: &'static str, document: Value) -> Response {
+    let mut headers = HeaderMap::new();
+    headers.append(CONTENT_TYPE, HeaderValue::from_static(content_type));
+    (headers, document.to_string()).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct WebfingerQuery {
+    resource: String,
+}
+
+pub async fn webfinger(Query(query): Query<WebfingerQuery>) -> Response {
+    if query.resource == ACCOUNT || query.resource == ACTOR_ID {
+        json_response("application/jrd+json", activitypub::webfinger())
+    } else {
+        super::handle_404().await
+    }
+}
+
+pub async fn actor(Extension(activitypub): Extension<Arc<ActivityPub>>) -> Response {
+    json_response(ACTIVITY_JSON, activitypub.actor())
+}
+
+pub async fn outbox(
+    Query(query): Query<PageQuery>,
+    Extension(content): Extension<Arc<ContentIndex>>,
+) -> Response {
+    let posts = content.published_feed_posts();
+    match activitypub::outbox(&posts, query.page) {
+        Some(outbox) => json_response(ACTIVITY_JSON, outbox),
+        None => super::handle_404().await,
+    }
+}
+
+pub async fn followers(Extension(activitypub): Extension<Arc<ActivityPub>>) -> Response {
+    json_response(ACTIVITY_JSON, activitypub.followers())
+}
+
+pub async fn post(
+    Path(path): Path<String>,
+    Extension(content): Extension<Arc<ContentIndex>>,
+) -> Response {
+    let url = format!("/blog/{path}");
+    let posts = content.published_feed_posts();
+    match posts.iter().find(|post| post.post.url() == url) {
+        Some(post) => json_response(
+            ACTIVITY_JSON,
+            activitypub::with_context(activitypub::article(post)),
+        ),
+        None => super::handle_404().await,
+    }
+}
+
+pub async fn inbox(
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    Extension(activitypub): Extension<Arc<ActivityPub>>,
+    body: Bytes,
+) -> Response {
+    let path = uri
+        .path_and_query()
+        .map_or(uri.path(), |path| path.as_str());
+    match activitypub.receive(&method, path, &headers, &body).await {
+        Ok(()) => StatusCode::ACCEPTED.into_response(),
+        Err(e) => {
+            debug!(?e, "rejected activity");
+            let status = match e {
+                WebsiteError::Signature(_) => StatusCode::UNAUTHORIZED,
+                WebsiteError::ActivityPub(_) | WebsiteError::Json(_) => StatusCode::BAD_REQUEST,
+                // the sender's key or actor couldn't be fetched
+                WebsiteError::ReqwestError(_) => StatusCode::UNAUTHORIZED,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            (status, e.to_string()).into_response()
+        }
+    }
+}
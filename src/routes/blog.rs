@@ -1,35 +1,65 @@
-use atom_syndication::{
-    Content, EntryBuilder, FixedDateTime, Generator, LinkBuilder, Person, Text,
-};
+use std::sync::Arc;
+
+use hmac::{Hmac, KeyInit, Mac};
+use lol_html::{element, rewrite_str, RewriteStrSettings};
 use once_cell::sync::Lazy;
 use regex::Regex;
-use reqwest::header::CONTENT_TYPE;
-use rss::ItemBuilder;
 use rust_embed::RustEmbed;
+use serde::Deserialize;
+use sha2::Sha256;
 
 use axum::{
-    extract::Path,
-    http::{HeaderMap, HeaderValue},
-    response::IntoResponse,
+    extract::{Extension, Path, Query},
+    http::header::LINK,
+    response::{IntoResponse, Response},
 };
-use time::{format_description::well_known::Rfc2822, Date, Month, OffsetDateTime, Time};
+use time::{Date, Month, OffsetDateTime};
 
+use super::feeds::{EntryStats, FeedEntry, FeedFormat, PageQuery};
 use crate::{
-    markdown,
-    templates::{BlogIndexTemplate, BlogPostTemplate, HtmlTemplate},
+    conditional::ContentUpdated,
+    content::ContentIndex,
+    error::{ContentError, ContentErrorKind, ContentErrors},
+    markdown::{self, ChangelogEntry},
+    templates::{
+        self, BlogIndexTemplate, BlogPostTemplate, BlogSeriesTemplate, BlogTagTemplate,
+        BlogTagsTemplate, HtmlTemplate,
+    },
+    webmention::Webmentions,
 };
 
 #[derive(RustEmbed)]
 #[folder = "blog/"]
 pub struct BlogAssets;
 
+const WORDS_PER_MINUTE: usize = 200;
+const POSTS_PER_PAGE: usize = 10;
+
 pub struct BlogPost {
     pub year: String,
     pub month: String,
     pub day: String,
     pub slug: String,
+    posted: OffsetDateTime,
     pub title: String,
     pub description: String,
+    pub tags: Vec<String>,
+    pub series: Option<String>,
+    pub series_part: Option<u32>,
+    /// Drafts are never published, and can only be viewed with a preview link.
+    pub draft: bool,
+    /// If set, the post is hidden until this time.
+    pub publish_at: Option<OffsetDateTime>,
+    /// When the post was last changed, if it has been since it was posted.
+    pub updated: Option<OffsetDateTime>,
+    /// Oldest first.
+    pub changelog: Vec<ChangelogEntry>,
+    /// The server and ID of the fediverse post to show replies to as comments.
+    pub comments: Option<(String, String)>,
+    /// Number of words in the post, not counting code blocks.
+    pub word_count: usize,
+    /// Every page linked to from the post, with site-local links as paths.
+    pub links: Vec<String>,
     pub rendered: String,
 }
 
@@ -52,240 +82,342 @@ impl BlogPost {
             day = self.day
         )
     }
+
+    /// Midnight UTC on the date the post was made.
+    pub fn posted(&self) -> OffsetDateTime {
+        self.posted
+    }
+
+    /// When the post last changed, whether that was an edit or the post itself.
+    pub fn last_modified(&self) -> OffsetDateTime {
+        self.changelog
+            .iter()
+            .map(|entry| entry.date)
+            .chain(self.updated)
+            .fold(self.posted(), OffsetDateTime::max)
+    }
+
+    /// Estimated reading time in minutes, rounded up.
+    pub fn reading_time(&self) -> usize {
+        self.word_count.div_ceil(WORDS_PER_MINUTE).max(1)
+    }
+
+    pub fn is_published(&self, now: OffsetDateTime) -> bool {
+        !self.draft && self.publish_at.is_none_or(|publish_at| publish_at <= now)
+    }
+}
+
+impl FeedEntry for BlogPost {
+    fn url(&self) -> String {
+        self.url()
+    }
+
+    fn title(&self) -> &str {
+        &self.title
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    fn rendered(&self) -> &str {
+        &self.rendered
+    }
+
+    fn published(&self) -> OffsetDateTime {
+        self.posted()
+    }
+
+    fn updated(&self) -> OffsetDateTime {
+        self.last_modified()
+    }
+
+    fn stats(&self) -> Option<EntryStats> {
+        Some(EntryStats {
+            word_count: self.word_count,
+            reading_time: self.reading_time(),
+        })
+    }
 }
 
-fn load_post(filename: &str) -> Option<BlogPost> {
+/// Problems that don't stop the post from loading are added to `warnings`.
+pub(crate) fn load_post(
+    filename: &str,
+    warnings: &mut Vec<ContentError>,
+) -> Result<BlogPost, ContentErrors> {
+    let path = format!("blog/{filename}");
     static NAME_REGEX: Lazy<Regex> =
         Lazy::new(|| Regex::new(r"([0-9]{4})-([0-9]{2})-([0-9]{2})-([a-z0-9\-]+)\.md$").unwrap());
-    if let Some(captures) = NAME_REGEX.captures(filename) {
-        let (year, month, day) = (
-            captures.get(1).unwrap().as_str().to_string(),
-            captures.get(2).unwrap().as_str().to_string(),
-            captures.get(3).unwrap().as_str().to_string(),
-        );
-        let slug = captures.get(4).unwrap().as_str().to_string();
-        if let Some(asset) = BlogAssets::get(filename) {
-            let (metadata, html) =
-                markdown::render_markdown(std::str::from_utf8(&asset.data).unwrap());
-            Some(BlogPost {
-                year,
-                month,
-                day,
-                slug,
-                title: metadata.title,
-                description: metadata.description,
-                rendered: html,
-            })
-        } else {
-            None
-        }
-    } else {
-        None
-    }
+    let captures = NAME_REGEX
+        .captures(filename)
+        .ok_or_else(|| ContentErrorKind::Filename("YYYY-MM-DD-slug.md").at(&path, 1))?;
+    let (year, month, day) = (
+        captures.get(1).unwrap().as_str().to_string(),
+        captures.get(2).unwrap().as_str().to_string(),
+        captures.get(3).unwrap().as_str().to_string(),
+    );
+    let slug = captures.get(4).unwrap().as_str().to_string();
+    let posted = Month::try_from(month.parse::<u8>().unwrap())
+        .and_then(|m| Date::from_calendar_date(year.parse().unwrap(), m, day.parse().unwrap()))
+        .map_err(|e| ContentErrorKind::from(e).at(&path, 1))?
+        .midnight()
+        .assume_utc();
+
+    let asset = BlogAssets::get(filename).ok_or_else(|| ContentErrorKind::Missing.at(&path, 1))?;
+    let markdown =
+        std::str::from_utf8(&asset.data).map_err(|_| ContentErrorKind::Utf8.at(&path, 1))?;
+    let rendered = markdown::render_markdown(&path, markdown)?;
+    warnings.extend(rendered.warnings);
+    let mut metadata = rendered.metadata;
+    metadata.changelog.sort_by_key(|entry| entry.date);
+    Ok(BlogPost {
+        year,
+        month,
+        day,
+        slug,
+        posted,
+        title: metadata.title,
+        description: metadata.description,
+        tags: metadata.tags,
+        series: metadata.series,
+        series_part: metadata.series_part,
+        draft: metadata.draft,
+        publish_at: metadata.publish_at,
+        updated: metadata.updated,
+        changelog: metadata.changelog,
+        comments: metadata.comments,
+        word_count: rendered.word_count,
+        links: outbound_links(&rendered.html),
+        rendered: rendered.html,
+    })
 }
 
-struct FeedPost {
-    post: BlogPost,
-    html: String,
+fn outbound_links(html: &str) -> Vec<String> {
+    let mut links = vec![];
+    rewrite_str(
+        html,
+        RewriteStrSettings::new().append_element_content_handler(element!("a[href]", |el| {
+            let href = el.get_attribute("href").unwrap_or_default();
+            // links to a section of a page count as links to the whole page
+            let href = href.split('#').next().unwrap_or_default();
+            let href = href.strip_prefix("https://ashhhleyyy.dev").unwrap_or(href);
+            if !href.is_empty() {
+                links.push(href.to_owned());
+            }
+            Ok(())
+        })),
+    )
+    .expect("failed to collect links");
+    links.sort();
+    links.dedup();
+    links
 }
 
-impl FeedPost {
-    async fn from(post: BlogPost) -> Self {
-        let html = crate::templates::rewrite_html(&post.url(), &post.rendered).await;
-        Self { post, html }
+/// Signs and verifies preview links, which let unpublished posts be shared before they go live.
+pub struct PreviewKey(Hmac<Sha256>);
+
+impl PreviewKey {
+    pub fn new(secret: &str) -> Self {
+        Self(Hmac::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length"))
+    }
+
+    fn sign(&self, url: &str) -> String {
+        let mut mac = self.0.clone();
+        mac.update(url.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    pub fn preview_url(&self, url: &str) -> String {
+        format!("{url}?preview={}", self.sign(url))
+    }
+
+    pub fn verify(&self, url: &str, token: &str) -> bool {
+        let Ok(signature) = hex::decode(token) else {
+            return false;
+        };
+        let mut mac = self.0.clone();
+        mac.update(url.as_bytes());
+        mac.verify_slice(&signature).is_ok()
     }
 }
 
-fn list_posts() -> Vec<BlogPost> {
-    let mut posts = BlogAssets::iter()
-        .filter_map(|path| load_post(&path))
-        .collect::<Vec<_>>();
-    posts.sort_by_key(|p| p.date());
-    posts.reverse();
-    posts
+fn index_page_url(page: usize) -> String {
+    match page {
+        1 => "/blog/".to_owned(),
+        page => format!("/blog/?page={page}"),
+    }
 }
 
-async fn posts_feed() -> Vec<FeedPost> {
-    let posts = list_posts();
-    let mut feed = Vec::with_capacity(posts.len());
-    for post in posts {
-        feed.push(FeedPost::from(post).await);
+pub async fn index(
+    Query(query): Query<PageQuery>,
+    Extension(content): Extension<Arc<ContentIndex>>,
+) -> Response {
+    let published = content.published();
+    let page = query.page.unwrap_or(1);
+    let page_count = published.posts.len().div_ceil(POSTS_PER_PAGE).max(1);
+    if page == 0 || page > page_count {
+        return super::handle_404().await;
     }
-    feed
+
+    let start = (page - 1) * POSTS_PER_PAGE;
+    let end = (start + POSTS_PER_PAGE).min(published.posts.len());
+    HtmlTemplate::new(
+        "/blog/",
+        BlogIndexTemplate {
+            posts: &published.posts[start..end],
+            newer_page: (page > 1).then(|| index_page_url(page - 1)),
+            older_page: (page < page_count).then(|| index_page_url(page + 1)),
+        },
+    )
+    .into_response()
+    .await
+}
+
+pub async fn rss(
+    Query(query): Query<PageQuery>,
+    Extension(content): Extension<Arc<ContentIndex>>,
+) -> Response {
+    let published = content.published();
+    published.feeds.response(FeedFormat::Rss, &query).await
 }
 
-pub async fn index() -> impl IntoResponse {
-    let posts = list_posts();
-    HtmlTemplate::new("/blog/", BlogIndexTemplate { posts })
+pub async fn atom(
+    Query(query): Query<PageQuery>,
+    Extension(content): Extension<Arc<ContentIndex>>,
+) -> Response {
+    let published = content.published();
+    published.feeds.response(FeedFormat::Atom, &query).await
+}
+
+pub async fn json(
+    Query(query): Query<PageQuery>,
+    Extension(content): Extension<Arc<ContentIndex>>,
+) -> Response {
+    let published = content.published();
+    published.feeds.response(FeedFormat::Json, &query).await
+}
+
+pub async fn tags(Extension(content): Extension<Arc<ContentIndex>>) -> impl IntoResponse {
+    let published = content.published();
+    let tags = published
+        .tags
+        .iter()
+        .map(|(name, tag)| (name.as_str(), tag.posts.len()))
+        .collect();
+    HtmlTemplate::new("/blog/tags/", BlogTagsTemplate { tags })
         .into_response()
         .await
 }
 
-fn month_from_index(index: u8) -> Month {
-    match index {
-        1 => Month::January,
-        2 => Month::February,
-        3 => Month::March,
-        4 => Month::April,
-        5 => Month::May,
-        6 => Month::June,
-        7 => Month::July,
-        8 => Month::August,
-        9 => Month::September,
-        10 => Month::October,
-        11 => Month::November,
-        12 => Month::December,
-        _ => panic!("invalid month"),
+pub async fn tag(
+    Path(tag): Path<String>,
+    Query(query): Query<PageQuery>,
+    Extension(content): Extension<Arc<ContentIndex>>,
+) -> Response {
+    let published = content.published();
+    if let Some((tag, format)) = FeedFormat::strip_extension(&tag) {
+        return match published.tags.get(tag) {
+            Some(tag) => tag.feeds.response(format, &query).await,
+            None => super::handle_404().await,
+        };
     }
-}
 
-pub async fn rss() -> impl IntoResponse {
-    let posts = posts_feed().await;
-    let mut builder = rss::ChannelBuilder::default();
-
-    builder
-        .title("ash's blog")
-        .link("https://ashhhleyyy.dev")
-        .description("random words i write for people to read")
-        .generator(Some(
-            "ashhhleyyy.dev/1.0 (+https://git.ashhhleyyy.dev/mirror/website)".to_owned(),
-        ))
-        .last_build_date(crate::SERVER_START_TIME.format(&Rfc2822).unwrap());
-    for post in posts {
-        builder.item(
-            ItemBuilder::default()
-                .title(post.post.title.clone())
-                .link(format!("https://ashhhleyyy.dev{}", post.post.url()))
-                .description(post.post.description.clone())
-                .guid(
-                    rss::GuidBuilder::default()
-                        .value(post.post.url())
-                        .permalink(false)
-                        .build(),
-                )
-                .pub_date(
-                    OffsetDateTime::new_utc(
-                        Date::from_calendar_date(
-                            post.post.year.parse().unwrap(),
-                            month_from_index(post.post.month.parse().unwrap()),
-                            post.post.day.parse().unwrap(),
-                        )
-                        .unwrap(),
-                        Time::from_hms(0, 0, 0).unwrap(),
-                    )
-                    .format(&Rfc2822)
-                    .unwrap(),
-                )
-                .content(post.html)
-                .build(),
-        );
+    if let Some(tagged) = published.tags.get(&tag) {
+        HtmlTemplate::new(
+            format!("/blog/tags/{tag}"),
+            BlogTagTemplate {
+                tag: &tag,
+                posts: &tagged.posts,
+            },
+        )
+        .into_response()
+        .await
+    } else {
+        super::handle_404().await
     }
-    let feed = builder.build();
-    let headers = {
-        let mut headers = HeaderMap::new();
-        headers.append(
-            CONTENT_TYPE,
-            HeaderValue::from_static("application/rss+xml"),
-        );
-        headers
-    };
-    (headers, feed.to_string())
 }
 
-pub async fn atom() -> impl IntoResponse {
-    let posts = posts_feed().await;
-    let mut builder = atom_syndication::FeedBuilder::default();
-
-    builder
-        .title(Text::plain("ash's blog"))
-        .id("https://ashhhleyyy.dev")
-        // hate
-        .updated(
-            FixedDateTime::parse_from_rfc2822(&crate::SERVER_START_TIME.format(&Rfc2822).unwrap())
-                .unwrap(),
-        )
-        .author(Person {
-            name: "ashhhleyyy".to_owned(),
-            uri: Some("https://ashhhleyyy.dev".to_owned()),
-            ..Default::default()
-        })
-        .generator(Generator {
-            value: "ashhhleyyy.dev".to_owned(),
-            uri: Some("https://git.ashhhleyyy.dev/mirror/website".to_owned()),
-            version: Some("1.0".to_owned()),
-        })
-        .icon("https://cdn.ashhhleyyy.dev/files/ashhhleyyy-assets/images/pfp.png".to_owned())
-        .link(
-            LinkBuilder::default()
-                .href("https://ashhhleyyy.dev/blog.atom")
-                .rel("self")
-                .build(),
-        )
-        .link(
-            LinkBuilder::default()
-                .href("https://ashhhleyyy.dev/")
-                .build(),
+pub async fn series(
+    Path(name): Path<String>,
+    Extension(content): Extension<Arc<ContentIndex>>,
+) -> Response {
+    let published = content.published();
+    if let Some(posts) = published.series.get(&name) {
+        HtmlTemplate::new(
+            format!("/blog/series/{name}"),
+            BlogSeriesTemplate { name: &name, posts },
         )
-        .logo("https://cdn.ashhhleyyy.dev/files/ashhhleyyy-assets/images/pfp.png".to_owned())
-        .subtitle(Text::plain("random words i write for people to read"))
-        .base("https://ashhhleyyy.dev".to_owned());
-
-    for post in posts {
-        let posted = FixedDateTime::parse_from_rfc3339(&format!(
-            "{}-{}-{}T00:00:00Z",
-            post.post.year, post.post.month, post.post.day
-        ))
-        .unwrap();
-        let url = format!("https://ashhhleyyy.dev{}", post.post.url());
-        builder.entry(
-            EntryBuilder::default()
-                .title(Text::plain(&post.post.title))
-                .id(post.post.url())
-                .updated(posted)
-                .author(Person {
-                    name: "ashhhleyyy".to_owned(),
-                    uri: Some("https://ashhhleyyy.dev".to_owned()),
-                    ..Default::default()
-                })
-                .link(LinkBuilder::default().href(&url).build())
-                .published(posted)
-                .summary(Text::plain(post.post.description))
-                .content(Content {
-                    base: Some(url.clone()),
-                    src: Some(url.clone()),
-                    value: Some(post.html),
-                    content_type: Some("html".to_string()),
-                    ..Default::default()
-                })
-                .build(),
-        );
+        .into_response()
+        .await
+    } else {
+        super::handle_404().await
     }
+}
 
-    let feed = builder.build();
-    let headers = {
-        let mut headers = HeaderMap::new();
-        headers.append(
-            CONTENT_TYPE,
-            HeaderValue::from_static("application/atom+xml"),
-        );
-        headers
-    };
-    (headers, feed.to_string())
+#[derive(Deserialize)]
+pub struct PostQuery {
+    preview: Option<String>,
 }
 
-pub async fn post(Path(path): Path<String>) -> impl IntoResponse {
-    if let Some(post) = load_post(&format!("{path}.md")) {
-        HtmlTemplate::new(
-            format!("/blog/{path}"),
+pub async fn post(
+    Path(path): Path<String>,
+    Query(query): Query<PostQuery>,
+    Extension(content): Extension<Arc<ContentIndex>>,
+    Extension(webmentions): Extension<Arc<Webmentions>>,
+) -> impl IntoResponse {
+    let url = format!("/blog/{path}");
+    let post = content.post(&url).filter(|post| {
+        post.is_published(OffsetDateTime::now_utc())
+            || query
+                .preview
+                .as_ref()
+                .is_some_and(|token| content.verify_preview(&url, token))
+    });
+    if let Some(post) = post {
+        let published = content.published();
+        let series = post.series.as_ref().and_then(|name| {
+            published
+                .series
+                .get(name)
+                .map(|parts| (name.as_str(), parts.as_slice()))
+        });
+        let comments = match &post.comments {
+            Some((server, id)) => templates::load_comments(server, id),
+            None => None,
+        };
+        let page = HtmlTemplate::new(
+            &url,
             BlogPostTemplate {
-                title: post.title.clone(),
+                title: &post.title,
                 date: post.date(),
-                description: post.description,
-                content: post.rendered,
+                updated: (post.last_modified() > post.posted())
+                    .then(|| post.last_modified().date().to_string()),
+                changelog: &post.changelog,
+                description: &post.description,
+                url: &url,
+                tags: &post.tags,
+                series,
+                word_count: post.word_count,
+                reading_time: post.reading_time(),
+                preview: !post.is_published(OffsetDateTime::now_utc()),
+                navigation: published.navigation.get(&url),
+                mentions: webmentions.for_post(&url),
+                comments,
+                content: &post.rendered,
             },
         )
         .into_response()
-        .await
+        .await;
+        (
+            [(LINK, super::webmention::LINK_HEADER)],
+            Extension(ContentUpdated(post.last_modified())),
+            page,
+        )
+            .into_response()
     } else {
         super::handle_404().await
     }
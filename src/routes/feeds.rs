@@ -0,0 +1,734 @@
+use std::{cell::RefCell, rc::Rc, sync::Arc};
+
+use atom_syndication::{
+    extension::{self, ExtensionMap},
+    Content, EntryBuilder, FixedDateTime, Generator, LinkBuilder, Person, Text,
+};
+use axum::{
+    body::Bytes,
+    extract::Extension,
+    http::{header::ETAG, HeaderMap, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use lol_html::{
+    element, html_content::ContentType, rewrite_str, EndTagHandler, RewriteStrSettings,
+};
+use reqwest::{header::CONTENT_TYPE, Url};
+use rss::ItemBuilder;
+use serde::{Deserialize, Serialize};
+use time::{
+    format_description::well_known::{Rfc2822, Rfc3339},
+    OffsetDateTime,
+};
+
+use super::blog::BlogPost;
+use crate::{
+    conditional::{self, ContentUpdated},
+    search,
+};
+
+pub(crate) const SITE_ORIGIN: &str = "https://ashhhleyyy.dev";
+
+pub(crate) const FEED_ICON: &str =
+    "https://cdn.ashhhleyyy.dev/files/ashhhleyyy-assets/images/pfp.png";
+
+const ATOM_NAMESPACE: &str = "http://www.w3.org/2005/Atom";
+
+/// Namespace for our own additions to the atom and JSON feeds, such as reading time.
+const ATOM_EXTENSION_NAMESPACE: (&str, &str) = ("ashhhleyyy", "https://ashhhleyyy.dev/ns/feed");
+
+/// Something that can be published in a feed, such as a blog post or a project.
+pub(crate) trait FeedEntry {
+    /// Site-local URL of the entry's page, which doubles as its ID.
+    fn url(&self) -> String;
+    fn title(&self) -> &str;
+    fn description(&self) -> &str;
+    fn tags(&self) -> &[String];
+    /// The entry's content, before `rewrite_html` has been run on it.
+    fn rendered(&self) -> &str;
+    fn published(&self) -> OffsetDateTime;
+    fn updated(&self) -> OffsetDateTime;
+    fn stats(&self) -> Option<EntryStats>;
+}
+
+pub(crate) struct EntryStats {
+    pub word_count: usize,
+    pub reading_time: usize,
+}
+
+/// Channel-level details for one of the feeds.
+pub(crate) struct FeedInfo {
+    pub title: String,
+    pub description: &'static str,
+    pub id: String,
+    /// The HTML page this feed is an alternate version of.
+    pub link: String,
+    /// Absolute URL of the feed, without the `.rss`/`.atom`/`.json` extension.
+    pub feed_url: String,
+}
+
+impl FeedInfo {
+    pub fn blog() -> Self {
+        Self {
+            title: "ash's blog".to_owned(),
+            description: "random words i write for people to read",
+            id: "https://ashhhleyyy.dev".to_owned(),
+            link: "https://ashhhleyyy.dev/".to_owned(),
+            feed_url: "https://ashhhleyyy.dev/blog".to_owned(),
+        }
+    }
+
+    pub fn tag(tag: &str) -> Self {
+        let link = format!("https://ashhhleyyy.dev/blog/tags/{tag}");
+        Self {
+            title: format!("ash's blog: #{tag}"),
+            description: "random words i write for people to read",
+            id: link.clone(),
+            feed_url: link.clone(),
+            link,
+        }
+    }
+
+    pub fn projects() -> Self {
+        Self {
+            title: "ash's projects".to_owned(),
+            description: "things i've made",
+            id: "https://ashhhleyyy.dev/projects/".to_owned(),
+            link: "https://ashhhleyyy.dev/projects/".to_owned(),
+            feed_url: "https://ashhhleyyy.dev/projects".to_owned(),
+        }
+    }
+
+    /// URL of one page of a paged feed, counting from 1.
+    fn page_url(&self, extension: &str, page: usize) -> String {
+        match page {
+            1 => format!("{}.{extension}", self.feed_url),
+            page => format!("{}.{extension}?page={page}", self.feed_url),
+        }
+    }
+}
+
+/// An entry along with its content prepared for feeds.
+pub(crate) struct FeedPost<T = BlogPost> {
+    pub post: Arc<T>,
+    html: String,
+}
+
+impl<T: FeedEntry> FeedPost<T> {
+    async fn from(post: Arc<T>) -> Self {
+        let url = post.url();
+        let html = crate::templates::rewrite_html(&url, post.rendered()).await;
+        let html = make_portable(&html, &url);
+        Self { post, html }
+    }
+
+    pub(crate) fn html(&self) -> &str {
+        &self.html
+    }
+
+    pub(crate) async fn render_all(posts: &[Arc<T>]) -> Vec<Self> {
+        let mut feed = Vec::with_capacity(posts.len());
+        for post in posts {
+            feed.push(Self::from(post.clone()).await);
+        }
+        feed
+    }
+}
+
+/// Adjusts a page's HTML so it still works when embedded in a feed reader: every URL is made
+/// absolute, `<picture>` is flattened to its plain image, IDs are made unique to the entry so
+/// footnote links don't jump into other entries, and MathML, which few readers support, is
+/// replaced with its plain-text form. Diagrams are coloured with the reader's text colour and
+/// icons are sized to the text, as the site's stylesheet isn't there to do it.
+fn make_portable(html: &str, url: &str) -> String {
+    let base = Url::parse(SITE_ORIGIN)
+        .and_then(|origin| origin.join(url))
+        .expect("entry URLs are valid");
+    let resolve = |url: &str| base.join(url).map_or_else(|_| url.to_owned(), String::from);
+    let slug = url.trim_end_matches('/').rsplit('/').next().unwrap_or(url);
+    let unique_id = |id: &str| format!("{slug}-{id}");
+
+    // the image to use for the current picture, if it doesn't have an <img> of its own
+    let fallback = Rc::new(RefCell::new(None::<String>));
+
+    rewrite_str(
+        html,
+        RewriteStrSettings::new()
+            .append_element_content_handler(element!("picture", |el| {
+                el.remove_and_keep_content();
+                *fallback.borrow_mut() = None;
+                let fallback = fallback.clone();
+                let insert_fallback: EndTagHandler = Box::new(move |end| {
+                    if let Some(src) = fallback.borrow_mut().take() {
+                        end.before(&maud::html! { img src=(src); }.0, ContentType::Html);
+                    }
+                    Ok(())
+                });
+                el.on_end_tag(insert_fallback)
+            }))
+            .append_element_content_handler(element!("picture source", |el| {
+                let mut fallback = fallback.borrow_mut();
+                if fallback.is_none() {
+                    *fallback = el
+                        .get_attribute("srcset")
+                        .and_then(|srcset| Some(srcset.split_whitespace().next()?.to_owned()))
+                        .map(|src| resolve(&src));
+                }
+                el.remove();
+                Ok(())
+            }))
+            .append_element_content_handler(element!("picture img", |_| {
+                *fallback.borrow_mut() = None;
+                Ok(())
+            }))
+            .append_element_content_handler(element!("math", |el| {
+                // attributes come back still escaped, and the text is escaped again when replaced
+                let text = search::unescape(&el.get_attribute("alttext").unwrap_or_default());
+                el.replace(&text, ContentType::Text);
+                Ok(())
+            }))
+            .append_element_content_handler(element!(".diagram svg *", |el| {
+                let (fill, stroke) = match el.tag_name().as_str() {
+                    "tspan" => return Ok(()),
+                    "text" | "polygon" => ("currentColor", None),
+                    _ => ("none", Some("currentColor")),
+                };
+                if !el.has_attribute("fill") {
+                    el.set_attribute("fill", fill)?;
+                }
+                if let Some(stroke) = stroke.filter(|_| !el.has_attribute("stroke")) {
+                    el.set_attribute("stroke", stroke)?;
+                }
+                Ok(())
+            }))
+            .append_element_content_handler(element!("svg.icon", |el| {
+                el.set_attribute("width", "1.2em")?;
+                el.set_attribute("height", "1.2em")?;
+                el.set_attribute("fill", "currentColor")?;
+                Ok(())
+            }))
+            .append_element_content_handler(element!("[id]", |el| {
+                let id = el.get_attribute("id").unwrap_or_default();
+                el.set_attribute("id", &unique_id(&id))?;
+                Ok(())
+            }))
+            .append_element_content_handler(element!("[href]", |el| {
+                let href = el.get_attribute("href").unwrap_or_default();
+                let href = match href.strip_prefix('#') {
+                    Some(id) => format!("#{}", unique_id(id)),
+                    None => resolve(&href),
+                };
+                el.set_attribute("href", &href)?;
+                Ok(())
+            }))
+            .append_element_content_handler(element!("[src]", |el| {
+                let src = el.get_attribute("src").unwrap_or_default();
+                el.set_attribute("src", &resolve(&src))?;
+                Ok(())
+            }))
+            .append_element_content_handler(element!("[srcset]", |el| {
+                let srcset = el.get_attribute("srcset").unwrap_or_default();
+                let srcset = srcset
+                    .split(',')
+                    .map(|candidate| {
+                        let candidate = candidate.trim();
+                        match candidate.split_once(char::is_whitespace) {
+                            Some((url, descriptor)) => format!("{} {descriptor}", resolve(url)),
+                            None => resolve(candidate),
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                el.set_attribute("srcset", &srcset)?;
+                Ok(())
+            })),
+    )
+    .expect("failed to rewrite feed html")
+}
+
+/// Pre-rendered feeds, ready to be sent as-is.
+pub struct Feeds {
+    /// Only the newest entries, as RSS has no way to link to older ones.
+    rss: FeedPage,
+    /// Every page of the atom feed, newest entries first. There is always at least one page.
+    atom: Vec<FeedPage>,
+    /// Every page of the JSON feed, paged in the same way as the atom feed.
+    json: Vec<FeedPage>,
+    /// The most recent change to any entry, on any page.
+    updated: OffsetDateTime,
+}
+
+/// A prebuilt page of a feed, which feed readers poll often, so its ETag is worked out once
+/// rather than on every request.
+struct FeedPage {
+    body: Bytes,
+    etag: HeaderValue,
+}
+
+impl FeedPage {
+    fn new(feed: String) -> Self {
+        let etag = conditional::etag_for(feed.as_bytes())
+            .parse()
+            .expect("hex is a valid etag");
+        Self {
+            body: Bytes::from(feed),
+            etag,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum FeedFormat {
+    Rss,
+    Atom,
+    Json,
+}
+
+impl FeedFormat {
+    /// Splits a feed format's extension off the end of a path, if it has one.
+    pub fn strip_extension(path: &str) -> Option<(&str, Self)> {
+        [
+            ("rss", Self::Rss),
+            ("atom", Self::Atom),
+            ("json", Self::Json),
+        ]
+        .into_iter()
+        .find_map(|(extension, format)| {
+            path.strip_suffix(extension)?
+                .strip_suffix('.')
+                .map(|path| (path, format))
+        })
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Rss => "application/rss+xml",
+            Self::Atom => "application/atom+xml",
+            Self::Json => "application/feed+json",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PageQuery {
+    pub page: Option<usize>,
+}
+
+impl Feeds {
+    pub(crate) fn build<T: FeedEntry>(
+        info: &FeedInfo,
+        posts: &[&FeedPost<T>],
+        page_size: usize,
+    ) -> Self {
+        let rss = FeedPage::new(build_rss(info, &posts[..posts.len().min(page_size)]));
+
+        let mut pages = posts.chunks(page_size).collect::<Vec<_>>();
+        if pages.is_empty() {
+            pages.push(&[]);
+        }
+        let build_pages = |build: fn(&FeedInfo, &[&FeedPost<T>], usize, usize) -> String| {
+            pages
+                .iter()
+                .enumerate()
+                .map(|(i, posts)| FeedPage::new(build(info, posts, i + 1, pages.len())))
+                .collect()
+        };
+
+        Self {
+            rss,
+            atom: build_pages(build_atom),
+            json: build_pages(build_json),
+            updated: feed_updated(posts),
+        }
+    }
+
+    /// Looks up a page of a feed, counting from 1.
+    fn page(&self, format: FeedFormat, page: usize) -> Option<&FeedPage> {
+        let pages = match format {
+            FeedFormat::Rss => std::slice::from_ref(&self.rss),
+            FeedFormat::Atom => &self.atom,
+            FeedFormat::Json => &self.json,
+        };
+        page.checked_sub(1).and_then(|i| pages.get(i))
+    }
+
+    /// Responds with a page of a feed, or a 404 if there is no such page.
+    pub(crate) async fn response(&self, format: FeedFormat, query: &PageQuery) -> Response {
+        match self.page(format, query.page.unwrap_or(1)) {
+            Some(feed) => {
+                let mut headers = HeaderMap::new();
+                headers.append(
+                    CONTENT_TYPE,
+                    HeaderValue::from_static(format.content_type()),
+                );
+                headers.append(ETAG, feed.etag.clone());
+                (
+                    headers,
+                    Extension(ContentUpdated(self.updated)),
+                    feed.body.clone(),
+                )
+                    .into_response()
+            }
+            None => super::handle_404().await,
+        }
+    }
+}
+
+/// The most recent change to any of the entries in a feed.
+fn feed_updated<T: FeedEntry>(posts: &[&FeedPost<T>]) -> OffsetDateTime {
+    posts
+        .iter()
+        .map(|post| post.post.updated())
+        .max()
+        .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+}
+
+fn fixed_date_time(date_time: OffsetDateTime) -> FixedDateTime {
+    FixedDateTime::parse_from_rfc3339(&date_time.format(&Rfc3339).unwrap()).unwrap()
+}
+
+fn build_rss<T: FeedEntry>(info: &FeedInfo, posts: &[&FeedPost<T>]) -> String {
+    let mut builder = rss::ChannelBuilder::default();
+
+    builder
+        .title(info.title.clone())
+        .link(info.link.clone())
+        .description(info.description)
+        .generator(Some(
+            "ashhhleyyy.dev/1.0 (+https://git.ashhhleyyy.dev/mirror/website)".to_owned(),
+        ))
+        .last_build_date(feed_updated(posts).format(&Rfc2822).unwrap())
+        .namespaces([("atom".to_owned(), ATOM_NAMESPACE.to_owned())]);
+    for post in posts {
+        builder.item(
+            ItemBuilder::default()
+                .title(post.post.title().to_owned())
+                .link(format!("https://ashhhleyyy.dev{}", post.post.url()))
+                .description(post.post.description().to_owned())
+                .categories(
+                    post.post
+                        .tags()
+                        .iter()
+                        .map(|tag| rss::Category {
+                            name: tag.clone(),
+                            domain: None,
+                        })
+                        .collect::<Vec<_>>(),
+                )
+                .guid(
+                    rss::GuidBuilder::default()
+                        .value(post.post.url())
+                        .permalink(false)
+                        .build(),
+                )
+                .pub_date(post.post.published().format(&Rfc2822).unwrap())
+                .extensions(rss_updated_extension(post.post.updated()))
+                .content(post.html.clone())
+                .build(),
+        );
+    }
+    builder.build().to_string()
+}
+
+/// RSS has no way to say when an item was updated, so borrow atom's.
+fn rss_updated_extension(updated: OffsetDateTime) -> rss::extension::ExtensionMap {
+    let updated = rss::extension::Extension {
+        name: "atom:updated".to_owned(),
+        value: Some(updated.format(&Rfc3339).unwrap()),
+        ..Default::default()
+    };
+    rss::extension::ExtensionMap::from([(
+        "atom".to_owned(),
+        [("updated".to_owned(), vec![updated])].into(),
+    )])
+}
+
+/// Builds one page of an atom feed, linking to the other pages as described in RFC 5005.
+fn build_atom<T: FeedEntry>(
+    info: &FeedInfo,
+    posts: &[&FeedPost<T>],
+    page: usize,
+    page_count: usize,
+) -> String {
+    let mut builder = atom_syndication::FeedBuilder::default();
+
+    builder
+        .title(Text::plain(&info.title))
+        .id(&info.id)
+        .updated(fixed_date_time(feed_updated(posts)))
+        .author(Person {
+            name: "ashhhleyyy".to_owned(),
+            uri: Some("https://ashhhleyyy.dev".to_owned()),
+            ..Default::default()
+        })
+        .generator(Generator {
+            value: "ashhhleyyy.dev".to_owned(),
+            uri: Some("https://git.ashhhleyyy.dev/mirror/website".to_owned()),
+            version: Some("1.0".to_owned()),
+        })
+        .icon(FEED_ICON.to_owned())
+        .link(
+            LinkBuilder::default()
+                .href(info.page_url("atom", page))
+                .rel("self")
+                .build(),
+        )
+        .link(LinkBuilder::default().href(&info.link).build())
+        .logo(FEED_ICON.to_owned())
+        .subtitle(Text::plain(info.description))
+        .namespace((
+            ATOM_EXTENSION_NAMESPACE.0.to_owned(),
+            ATOM_EXTENSION_NAMESPACE.1.to_owned(),
+        ))
+        .base("https://ashhhleyyy.dev".to_owned());
+
+    if page_count > 1 {
+        let mut link = |rel: &str, page: usize| {
+            builder.link(
+                LinkBuilder::default()
+                    .href(info.page_url("atom", page))
+                    .rel(rel)
+                    .build(),
+            );
+        };
+        link("first", 1);
+        link("last", page_count);
+        if page > 1 {
+            link("previous", page - 1);
+        }
+        if page < page_count {
+            link("next", page + 1);
+        }
+    }
+
+    for post in posts {
+        let url = format!("https://ashhhleyyy.dev{}", post.post.url());
+        builder.entry(
+            EntryBuilder::default()
+                .title(Text::plain(post.post.title()))
+                .id(post.post.url())
+                .updated(fixed_date_time(post.post.updated()))
+                .author(Person {
+                    name: "ashhhleyyy".to_owned(),
+                    uri: Some("https://ashhhleyyy.dev".to_owned()),
+                    ..Default::default()
+                })
+                .link(LinkBuilder::default().href(&url).build())
+                .published(fixed_date_time(post.post.published()))
+                .summary(Text::plain(post.post.description()))
+                .categories(
+                    post.post
+                        .tags()
+                        .iter()
+                        .map(|tag| atom_syndication::Category {
+                            term: tag.clone(),
+                            ..Default::default()
+                        })
+                        .collect::<Vec<_>>(),
+                )
+                .extensions(
+                    post.post
+                        .stats()
+                        .map(|stats| atom_stats_extension(&stats))
+                        .unwrap_or_default(),
+                )
+                .content(Content {
+                    base: Some(url.clone()),
+                    src: Some(url.clone()),
+                    value: Some(post.html.clone()),
+                    content_type: Some("html".to_string()),
+                    ..Default::default()
+                })
+                .build(),
+        );
+    }
+
+    builder.build().to_string()
+}
+
+fn atom_stats_extension(stats: &EntryStats) -> ExtensionMap {
+    let (prefix, _) = ATOM_EXTENSION_NAMESPACE;
+    let element = |name: &str, value: usize| {
+        (
+            name.to_owned(),
+            vec![extension::Extension {
+                name: format!("{prefix}:{name}"),
+                value: Some(value.to_string()),
+                ..Default::default()
+            }],
+        )
+    };
+    ExtensionMap::from([(
+        prefix.to_owned(),
+        [
+            element("wordCount", stats.word_count),
+            element("readingTime", stats.reading_time),
+        ]
+        .into(),
+    )])
+}
+
+#[derive(Serialize)]
+struct JsonFeed<'a> {
+    version: &'static str,
+    title: &'a str,
+    home_page_url: &'a str,
+    feed_url: String,
+    description: &'static str,
+    icon: &'static str,
+    authors: [JsonFeedAuthor; 1],
+    language: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_url: Option<String>,
+    items: Vec<JsonFeedItem<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonFeedAuthor {
+    name: &'static str,
+    url: &'static str,
+    avatar: &'static str,
+}
+
+const JSON_FEED_AUTHOR: JsonFeedAuthor = JsonFeedAuthor {
+    name: "ashhhleyyy",
+    url: "https://ashhhleyyy.dev",
+    avatar: FEED_ICON,
+};
+
+#[derive(Serialize)]
+struct JsonFeedItem<'a> {
+    id: String,
+    url: String,
+    title: &'a str,
+    content_html: &'a str,
+    summary: &'a str,
+    #[serde(with = "time::serde::rfc3339")]
+    date_published: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    date_modified: OffsetDateTime,
+    tags: &'a [String],
+    #[serde(rename = "_ashhhleyyy", skip_serializing_if = "Option::is_none")]
+    extension: Option<JsonFeedExtension>,
+}
+
+#[derive(Serialize)]
+struct JsonFeedExtension {
+    about: &'static str,
+    word_count: usize,
+    reading_time: usize,
+}
+
+/// Builds one page of a JSON Feed 1.1 feed.
+fn build_json<T: FeedEntry>(
+    info: &FeedInfo,
+    posts: &[&FeedPost<T>],
+    page: usize,
+    page_count: usize,
+) -> String {
+    let feed = JsonFeed {
+        version: "https://jsonfeed.org/version/1.1",
+        title: &info.title,
+        home_page_url: &info.link,
+        feed_url: info.page_url("json", page),
+        description: info.description,
+        icon: FEED_ICON,
+        authors: [JSON_FEED_AUTHOR],
+        language: "en",
+        next_url: (page < page_count).then(|| info.page_url("json", page + 1)),
+        items: posts
+            .iter()
+            .map(|post| JsonFeedItem {
+                id: post.post.url(),
+                url: format!("https://ashhhleyyy.dev{}", post.post.url()),
+                title: post.post.title(),
+                content_html: &post.html,
+                summary: post.post.description(),
+                date_published: post.post.published(),
+                date_modified: post.post.updated(),
+                tags: post.post.tags(),
+                extension: post.post.stats().map(|stats| JsonFeedExtension {
+                    about: ATOM_EXTENSION_NAMESPACE.1,
+                    word_count: stats.word_count,
+                    reading_time: stats.reading_time,
+                }),
+            })
+            .collect(),
+    };
+    serde_json::to_string(&feed).expect("failed to serialise JSON feed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_are_made_absolute() {
+        let html = make_portable(
+            r#"<a href="/projects/">a</a><a href="other">b</a><a href="https://example.com/">c</a><img src="../img.png">"#,
+            "/blog/post",
+        );
+        assert_eq!(
+            html,
+            r#"<a href="https://ashhhleyyy.dev/projects/">a</a><a href="https://ashhhleyyy.dev/blog/other">b</a><a href="https://example.com/">c</a><img src="https://ashhhleyyy.dev/img.png">"#
+        );
+    }
+
+    #[test]
+    fn srcset_candidates_are_made_absolute() {
+        let html = make_portable(
+            r#"<img srcset="/a.png 1x, /b.png 2x,/c.png">"#,
+            "/blog/post",
+        );
+        assert_eq!(
+            html,
+            r#"<img srcset="https://ashhhleyyy.dev/a.png 1x, https://ashhhleyyy.dev/b.png 2x, https://ashhhleyyy.dev/c.png">"#
+        );
+    }
+
+    #[test]
+    fn ids_and_fragment_links_are_unique_to_the_entry() {
+        let html = make_portable(
+            r##"<sup><a href="#fn-1" id="fnref-1">1</a></sup><li id="fn-1"><a href="#fnref-1">↩</a></li>"##,
+            "/blog/post/",
+        );
+        assert_eq!(
+            html,
+            r##"<sup><a href="#post-fn-1" id="post-fnref-1">1</a></sup><li id="post-fn-1"><a href="#post-fnref-1">↩</a></li>"##
+        );
+    }
+
+    #[test]
+    fn pictures_are_flattened_to_their_image() {
+        let with_img = make_portable(
+            r#"<picture><source srcset="/a.avif 1x" type="image/avif"><img src="/a.png" alt="a"></picture>"#,
+            "/blog/post",
+        );
+        assert_eq!(
+            with_img,
+            r#"<img src="https://ashhhleyyy.dev/a.png" alt="a">"#
+        );
+
+        let without_img = make_portable(
+            r#"<picture><source srcset="/a.avif 1x, /b.avif 2x"></picture>"#,
+            "/blog/post",
+        );
+        assert_eq!(without_img, r#"<img src="https://ashhhleyyy.dev/a.avif">"#);
+    }
+
+    #[test]
+    fn math_is_replaced_with_plain_text() {
+        let html = make_portable(
+            r#"<p><math alttext="x² &lt; y"><msup><mi>x</mi><mn>2</mn></msup></math></p>"#,
+            "/blog/post",
+        );
+        assert_eq!(html, "<p>x² &lt; y</p>");
+    }
+
+    #[test]
+    fn feed_pages_know_their_etag() {
+        let page = FeedPage::new("<feed></feed>".to_owned());
+        assert_eq!(page.body, "<feed></feed>");
+        assert_eq!(page.etag, conditional::etag_for(b"<feed></feed>"));
+    }
+}
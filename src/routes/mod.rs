@@ -1,13 +1,18 @@
+mod activitypub;
 mod assets;
 pub(crate) mod blog;
 // TODO: resurrect or yeet
 // mod extras;
-mod projects;
+pub(crate) mod feeds;
+pub(crate) mod projects;
+mod search;
+mod sitemap;
+mod webmention;
 
 use axum::{
     extract::Extension,
     response::{IntoResponse, Redirect, Response},
-    routing::get,
+    routing::{get, post},
     Router,
 };
 use reqwest::StatusCode;
@@ -92,14 +97,32 @@ pub fn build_router() -> Router {
         .route("/blog/", get(blog::index))
         .route("/blog.rss", get(blog::rss))
         .route("/blog.atom", get(blog::atom))
+        .route("/blog.json", get(blog::json))
+        .route("/blog/tags/", get(blog::tags))
+        .route("/blog/tags/{tag}", get(blog::tag))
+        .route("/blog/series/{name}", get(blog::series))
         .route("/blog/{post}", get(blog::post))
         .route("/projects/", get(projects::index))
+        .route("/projects.rss", get(projects::rss))
+        .route("/projects.atom", get(projects::atom))
+        .route("/projects.json", get(projects::json))
         .route("/projects/{year}/{project}", get(projects::project))
         //.route("/extras/:title", get(extras::page))
         .route("/me", get(links))
         .route("/assets-gen/background.svg", get(background))
         .route("/assets-gen/image.js", get(image_script))
+        .route("/sitemap.xml", get(sitemap::index))
+        .route("/sitemaps/{part}", get(sitemap::part))
+        .route("/search", get(search::page))
+        .route("/api/search", get(search::api))
         .route("/api/oembed", get(assets::oembed))
+        .route("/api/webmention", post(webmention::receive))
+        .route("/.well-known/webfinger", get(activitypub::webfinger))
+        .route("/activitypub/actor", get(activitypub::actor))
+        .route("/activitypub/inbox", post(activitypub::inbox))
+        .route("/activitypub/outbox", get(activitypub::outbox))
+        .route("/activitypub/followers", get(activitypub::followers))
+        .route("/activitypub/blog/{post}", get(activitypub::post))
         .layer(TraceLayer::new_for_http())
         .fallback(handle_404)
 }
@@ -1,11 +1,19 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
-use axum::{extract::Path, response::IntoResponse};
+use axum::{
+    extract::{Extension, Path, Query},
+    response::{IntoResponse, Response},
+};
 use once_cell::sync::Lazy;
 use regex::Regex;
 use rust_embed::RustEmbed;
+use time::{Date, OffsetDateTime};
 
+use super::feeds::{EntryStats, FeedEntry, FeedFormat, PageQuery};
 use crate::{
+    conditional::ContentUpdated,
+    content::ContentIndex,
+    error::{ContentError, ContentErrorKind, ContentErrors},
     markdown,
     templates::{HtmlTemplate, ProjectTemplate, ProjectsTemplate},
 };
@@ -15,15 +23,18 @@ use crate::{
 pub struct ProjectsAssets;
 
 pub struct Project {
-    year: String,
+    pub year: String,
     slug: String,
-    title: String,
-    description: String,
-    rendered: String,
+    pub title: String,
+    pub description: String,
+    /// When the project was made, or the start of its year if the frontmatter doesn't say.
+    pub date: OffsetDateTime,
+    pub updated: Option<OffsetDateTime>,
+    pub rendered: String,
 }
 
 impl Project {
-    fn url(&self) -> String {
+    pub fn url(&self) -> String {
         format!(
             "/projects/{year}/{slug}",
             year = self.year,
@@ -32,67 +43,148 @@ impl Project {
     }
 }
 
-fn load_project(filename: &str) -> Option<Project> {
-    static NAME_REGEX: Lazy<Regex> =
-        Lazy::new(|| Regex::new(r"([0-9]{4})-([a-z\-]+)\.md$").unwrap());
-    if let Some(captures) = NAME_REGEX.captures(filename) {
-        let (year, slug) = (
-            captures.get(1).unwrap().as_str().to_string(),
-            captures.get(2).unwrap().as_str().to_string(),
-        );
-        if let Some(asset) = ProjectsAssets::get(filename) {
-            let (metadata, html) =
-                markdown::render_markdown(std::str::from_utf8(&asset.data).unwrap());
-            Some(Project {
-                year,
-                slug,
-                title: metadata.title,
-                description: metadata.description,
-                rendered: html,
-            })
-        } else {
-            None
-        }
-    } else {
+impl FeedEntry for Project {
+    fn url(&self) -> String {
+        self.url()
+    }
+
+    fn title(&self) -> &str {
+        &self.title
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn tags(&self) -> &[String] {
+        &[]
+    }
+
+    fn rendered(&self) -> &str {
+        &self.rendered
+    }
+
+    fn published(&self) -> OffsetDateTime {
+        self.date
+    }
+
+    fn updated(&self) -> OffsetDateTime {
+        self.updated
+            .map_or(self.date, |updated| updated.max(self.date))
+    }
+
+    fn stats(&self) -> Option<EntryStats> {
         None
     }
 }
 
-pub async fn project(Path((year, slug)): Path<(String, String)>) -> impl IntoResponse {
-    if let Some(post) = load_project(&format!("{year}-{slug}.md")) {
-        HtmlTemplate::new(
-            format!("/projects/{year}/{slug}"),
+/// Problems that don't stop the project from loading are added to `warnings`.
+pub(crate) fn load_project(
+    filename: &str,
+    warnings: &mut Vec<ContentError>,
+) -> Result<Project, ContentErrors> {
+    let path = format!("projects/{filename}");
+    static NAME_REGEX: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"([0-9]{4})-([a-z\-]+)\.md$").unwrap());
+    let captures = NAME_REGEX
+        .captures(filename)
+        .ok_or_else(|| ContentErrorKind::Filename("YYYY-slug.md").at(&path, 1))?;
+    let (year, slug) = (
+        captures.get(1).unwrap().as_str().to_string(),
+        captures.get(2).unwrap().as_str().to_string(),
+    );
+
+    let asset =
+        ProjectsAssets::get(filename).ok_or_else(|| ContentErrorKind::Missing.at(&path, 1))?;
+    let markdown =
+        std::str::from_utf8(&asset.data).map_err(|_| ContentErrorKind::Utf8.at(&path, 1))?;
+    let rendered = markdown::render_markdown(&path, markdown)?;
+    warnings.extend(rendered.warnings);
+    let date = match rendered.metadata.date {
+        Some(date) => date,
+        None => Date::from_ordinal_date(year.parse().unwrap(), 1)
+            .map_err(|e| ContentErrorKind::from(e).at(&path, 1))?
+            .midnight()
+            .assume_utc(),
+    };
+    Ok(Project {
+        year,
+        slug,
+        title: rendered.metadata.title,
+        description: rendered.metadata.description,
+        date,
+        updated: rendered.metadata.updated,
+        rendered: rendered.html,
+    })
+}
+
+pub async fn project(
+    Path((year, slug)): Path<(String, String)>,
+    Extension(content): Extension<Arc<ContentIndex>>,
+) -> impl IntoResponse {
+    let url = format!("/projects/{year}/{slug}");
+    if let Some(project) = content.project(&url) {
+        let page = HtmlTemplate::new(
+            &url,
             ProjectTemplate {
-                title: post.title.clone(),
-                description: post.description,
-                content: post.rendered,
+                title: &project.title,
+                description: &project.description,
+                content: &project.rendered,
             },
         )
         .into_response()
-        .await
+        .await;
+        (Extension(ContentUpdated(project.updated())), page).into_response()
     } else {
         super::handle_404().await
     }
 }
 
-pub async fn index() -> impl IntoResponse {
+pub async fn index(Extension(content): Extension<Arc<ContentIndex>>) -> impl IntoResponse {
     let mut projects_by_year = HashMap::new();
-    for path in ProjectsAssets::iter() {
-        if let Some(project) = load_project(&path) {
-            let url = project.url();
-            projects_by_year
-                .entry(project.year)
-                .or_insert_with(Vec::new)
-                .push((project.title, url));
-        }
+    for project in &content.projects {
+        projects_by_year
+            .entry(project.year.as_str())
+            .or_insert_with(Vec::new)
+            .push((project.title.as_str(), project.url()));
     }
 
     let mut projects_by_year = projects_by_year.into_iter().collect::<Vec<_>>();
 
-    projects_by_year.sort_by_cached_key(|(year, _)| year.clone());
+    projects_by_year.sort_by_key(|(year, _)| *year);
     projects_by_year.reverse();
 
     HtmlTemplate::new("/projects/", ProjectsTemplate { projects_by_year })
         .into_response()
         .await
 }
+
+pub async fn rss(
+    Query(query): Query<PageQuery>,
+    Extension(content): Extension<Arc<ContentIndex>>,
+) -> Response {
+    content
+        .project_feeds
+        .response(FeedFormat::Rss, &query)
+        .await
+}
+
+pub async fn atom(
+    Query(query): Query<PageQuery>,
+    Extension(content): Extension<Arc<ContentIndex>>,
+) -> Response {
+    content
+        .project_feeds
+        .response(FeedFormat::Atom, &query)
+        .await
+}
+
+pub async fn json(
+    Query(query): Query<PageQuery>,
+    Extension(content): Extension<Arc<ContentIndex>>,
+) -> Response {
+    content
+        .project_feeds
+        .response(FeedFormat::Json, &query)
+        .await
+}
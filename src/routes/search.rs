@@ -0,0 +1,55 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Extension, Query},
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    content::ContentIndex,
+    search::SearchResult,
+    templates::{HtmlTemplate, SearchTemplate},
+};
+
+const MAX_RESULTS: usize = 20;
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    #[serde(default)]
+    q: String,
+}
+
+pub async fn page(
+    Query(query): Query<SearchQuery>,
+    Extension(content): Extension<Arc<ContentIndex>>,
+) -> impl IntoResponse {
+    let results = content.search(&query.q, MAX_RESULTS);
+    HtmlTemplate::new(
+        "/search",
+        SearchTemplate {
+            query: query.q,
+            results,
+        },
+    )
+    .into_response()
+    .await
+}
+
+#[derive(Serialize)]
+pub struct SearchResponse {
+    query: String,
+    results: Vec<SearchResult>,
+}
+
+pub async fn api(
+    Query(query): Query<SearchQuery>,
+    Extension(content): Extension<Arc<ContentIndex>>,
+) -> Json<SearchResponse> {
+    let results = content.search(&query.q, MAX_RESULTS);
+    Json(SearchResponse {
+        query: query.q,
+        results,
+    })
+}
@@ -0,0 +1,120 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Extension, Path},
+    http::{HeaderMap, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use reqwest::header::CONTENT_TYPE;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use super::feeds::FeedEntry;
+use crate::content::ContentIndex;
+
+/// The most URLs the sitemap protocol allows in one file. Past this, `/sitemap.xml` becomes an
+/// index of smaller sitemaps.
+const MAX_URLS: usize = 50_000;
+
+/// Pages registered in `build_router` that aren't generated from content.
+const STATIC_PAGES: &[&str] = &["/", "/me", "/about/words", "/about/music", "/attribution"];
+
+const SITEMAP_NAMESPACE: &str = "http://www.sitemaps.org/schemas/sitemap/0.9";
+
+struct SitemapUrl {
+    path: String,
+    last_modified: Option<OffsetDateTime>,
+}
+
+fn urls(content: &ContentIndex) -> Vec<SitemapUrl> {
+    let published = content.published();
+    let static_pages = STATIC_PAGES.iter().map(|path| SitemapUrl {
+        path: path.to_string(),
+        last_modified: None,
+    });
+    let blog_index = SitemapUrl {
+        path: "/blog/".to_owned(),
+        last_modified: published.posts.iter().map(|post| post.updated()).max(),
+    };
+    let posts = published.posts.iter().map(|post| SitemapUrl {
+        path: post.url(),
+        last_modified: Some(post.updated()),
+    });
+    let projects_index = SitemapUrl {
+        path: "/projects/".to_owned(),
+        last_modified: content
+            .projects
+            .iter()
+            .map(|project| project.updated())
+            .max(),
+    };
+    let projects = content.projects.iter().map(|project| SitemapUrl {
+        path: project.url(),
+        last_modified: Some(project.updated()),
+    });
+
+    static_pages
+        .chain([blog_index])
+        .chain(posts)
+        .chain([projects_index])
+        .chain(projects)
+        .collect()
+}
+
+fn xml_response(markup: maud::Markup) -> Response {
+    let mut headers = HeaderMap::new();
+    headers.append(CONTENT_TYPE, HeaderValue::from_static("application/xml"));
+    let xml = format!(r#"<?xml version="1.0" encoding="UTF-8"?>{}"#, markup.0);
+    (headers, xml).into_response()
+}
+
+fn urlset(urls: &[SitemapUrl]) -> Response {
+    xml_response(maud::html! {
+        urlset xmlns=(SITEMAP_NAMESPACE) {
+            @for url in urls {
+                url {
+                    loc { "https://ashhhleyyy.dev" (url.path) }
+                    @if let Some(last_modified) = url.last_modified {
+                        lastmod { (last_modified.format(&Rfc3339).unwrap()) }
+                    }
+                }
+            }
+        }
+    })
+}
+
+pub async fn index(Extension(content): Extension<Arc<ContentIndex>>) -> Response {
+    let urls = urls(&content);
+    if urls.len() <= MAX_URLS {
+        return urlset(&urls);
+    }
+
+    xml_response(maud::html! {
+        sitemapindex xmlns=(SITEMAP_NAMESPACE) {
+            @for (i, chunk) in urls.chunks(MAX_URLS).enumerate() {
+                sitemap {
+                    loc { "https://ashhhleyyy.dev/sitemaps/" (i + 1) ".xml" }
+                    @if let Some(last_modified) = chunk.iter().filter_map(|url| url.last_modified).max() {
+                        lastmod { (last_modified.format(&Rfc3339).unwrap()) }
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// One part of the sitemap, when there are too many URLs to fit in `/sitemap.xml`.
+pub async fn part(
+    Path(part): Path<String>,
+    Extension(content): Extension<Arc<ContentIndex>>,
+) -> Response {
+    let urls = urls(&content);
+    let chunk = part
+        .strip_suffix(".xml")
+        .and_then(|part| part.parse::<usize>().ok())
+        .and_then(|part| part.checked_sub(1))
+        .and_then(|i| urls.chunks(MAX_URLS).nth(i));
+    match chunk {
+        Some(chunk) if urls.len() > MAX_URLS => urlset(chunk),
+        _ => super::handle_404().await,
+    }
+}
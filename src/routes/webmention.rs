@@ -0,0 +1,75 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::Extension,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Form,
+};
+use reqwest::Url;
+use serde::Deserialize;
+use time::OffsetDateTime;
+
+use super::feeds::SITE_ORIGIN;
+use crate::{
+    apis::public::is_public_url,
+    content::ContentIndex,
+    webmention::{PendingMention, Webmentions},
+};
+
+/// Sent with every post, so other sites know where to send webmentions.
+pub const LINK_HEADER: &str = r#"<https://ashhhleyyy.dev/api/webmention>; rel="webmention""#;
+
+#[derive(Deserialize)]
+pub struct WebmentionForm {
+    #[serde(default)]
+    source: String,
+    #[serde(default)]
+    target: String,
+}
+
+fn bad_request(message: &'static str) -> Response {
+    (StatusCode::BAD_REQUEST, message).into_response()
+}
+
+/// Accepts a webmention for one of our posts, which is verified later on.
+pub async fn receive(
+    Extension(content): Extension<Arc<ContentIndex>>,
+    Extension(webmentions): Extension<Arc<Webmentions>>,
+    Form(form): Form<WebmentionForm>,
+) -> Response {
+    let (Ok(source), Ok(mut target)) = (Url::parse(&form.source), Url::parse(&form.target)) else {
+        return bad_request("source and target must be valid URLs");
+    };
+    if !is_public_url(&source) {
+        return bad_request("source must be a public http or https URL");
+    }
+    target.set_fragment(None);
+    if source == target {
+        return bad_request("source and target must be different");
+    }
+
+    let post = target
+        .as_str()
+        .strip_prefix(SITE_ORIGIN)
+        .and_then(|path| content.post(path))
+        .filter(|post| post.is_published(OffsetDateTime::now_utc()));
+    let Some(post) = post else {
+        return bad_request("target is not a post on this site");
+    };
+
+    let pending = PendingMention {
+        source,
+        target,
+        post: post.url(),
+    };
+    if webmentions.enqueue(pending) {
+        (StatusCode::ACCEPTED, "webmention queued for verification").into_response()
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "too many webmentions waiting to be verified, try again later",
+        )
+            .into_response()
+    }
+}
@@ -0,0 +1,329 @@
+use std::{collections::HashMap, ops::Range};
+
+use lol_html::{doc_text, rewrite_str, RewriteStrSettings};
+use serde::Serialize;
+
+const TITLE_WEIGHT: f64 = 5.0;
+const DESCRIPTION_WEIGHT: f64 = 3.0;
+const BODY_WEIGHT: f64 = 1.0;
+
+/// Roughly how many bytes of context to show either side of the first match in a snippet.
+const SNIPPET_CONTEXT: usize = 80;
+const SNIPPET_LENGTH: usize = 240;
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentKind {
+    Post,
+    Project,
+}
+
+struct Document {
+    kind: DocumentKind,
+    url: String,
+    title: String,
+    description: String,
+    text: String,
+}
+
+/// An in-memory inverted index over the rendered text of blog posts and projects.
+#[derive(Default)]
+pub struct SearchIndex {
+    documents: Vec<Document>,
+    /// Maps each term to the documents it appears in, and its weighted frequency there.
+    terms: HashMap<String, Vec<(usize, f64)>>,
+}
+
+#[derive(Serialize)]
+pub struct SearchResult {
+    pub kind: DocumentKind,
+    pub url: String,
+    pub title: String,
+    pub description: String,
+    /// An excerpt of the document as HTML, with matching terms wrapped in `<mark>`.
+    pub snippet: String,
+    pub score: f64,
+}
+
+impl SearchIndex {
+    pub fn add(
+        &mut self,
+        kind: DocumentKind,
+        url: String,
+        title: &str,
+        description: &str,
+        html: &str,
+    ) {
+        let id = self.documents.len();
+        let text = html_to_text(html);
+
+        let mut frequencies = HashMap::<String, f64>::new();
+        for (field, weight) in [
+            (title, TITLE_WEIGHT),
+            (description, DESCRIPTION_WEIGHT),
+            (text.as_str(), BODY_WEIGHT),
+        ] {
+            for (_, token) in tokenize(field) {
+                *frequencies.entry(token).or_default() += weight;
+            }
+        }
+        for (term, frequency) in frequencies {
+            self.terms.entry(term).or_default().push((id, frequency));
+        }
+
+        self.documents.push(Document {
+            kind,
+            url,
+            title: title.to_owned(),
+            description: description.to_owned(),
+            text,
+        });
+    }
+
+    /// Searches the index, ranking documents by TF-IDF and favouring those that match more of
+    /// the query. Documents for which `is_visible` returns false are skipped.
+    pub fn search(
+        &self,
+        query: &str,
+        limit: usize,
+        is_visible: impl Fn(&str) -> bool,
+    ) -> Vec<SearchResult> {
+        let mut query_terms = tokenize(query).map(|(_, token)| token).collect::<Vec<_>>();
+        query_terms.sort();
+        query_terms.dedup();
+        if query_terms.is_empty() {
+            return vec![];
+        }
+
+        let document_count = self.documents.len() as f64;
+        let mut scores = HashMap::<usize, (f64, usize)>::new();
+        for term in &query_terms {
+            let Some(postings) = self.terms.get(term) else {
+                continue;
+            };
+            let idf = (1.0 + document_count / postings.len() as f64).ln();
+            for &(id, frequency) in postings {
+                let (score, matched) = scores.entry(id).or_default();
+                *score += (1.0 + frequency.ln()) * idf;
+                *matched += 1;
+            }
+        }
+
+        let mut results = scores
+            .into_iter()
+            .filter(|(id, _)| is_visible(&self.documents[*id].url))
+            .map(|(id, (score, matched))| (id, score * matched as f64 / query_terms.len() as f64))
+            .collect::<Vec<_>>();
+        results.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        results.truncate(limit);
+
+        results
+            .into_iter()
+            .map(|(id, score)| {
+                let document = &self.documents[id];
+                SearchResult {
+                    kind: document.kind,
+                    url: document.url.clone(),
+                    title: document.title.clone(),
+                    description: document.description.clone(),
+                    snippet: snippet(&document.text, &query_terms),
+                    score,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Splits text into lowercase alphanumeric terms, along with where they are in the text.
+fn tokenize(text: &str) -> impl Iterator<Item = (Range<usize>, String)> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(move |word| {
+            let offset = word.as_ptr() as usize - text.as_ptr() as usize;
+            (offset..offset + word.len(), word.to_lowercase())
+        })
+}
+
+fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    rewrite_str(
+        html,
+        RewriteStrSettings::new().append_document_content_handler(doc_text!(|chunk| {
+            text.push_str(chunk.as_str());
+            Ok(())
+        })),
+    )
+    .expect("failed to extract text");
+
+    unescape(&text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Decodes the entities that show up in text chunks from `lol_html`, which are left as-is.
+pub(crate) fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// Cuts an excerpt out of `text` around the first matching term, and highlights every match.
+fn snippet(text: &str, terms: &[String]) -> String {
+    let matches = tokenize(text)
+        .filter(|(_, token)| terms.contains(token))
+        .map(|(range, _)| range)
+        .collect::<Vec<_>>();
+
+    let start = matches
+        .first()
+        .map(|range| range.start.saturating_sub(SNIPPET_CONTEXT))
+        .unwrap_or(0);
+    // start and end on a word boundary, so the excerpt doesn't begin mid-word
+    let start = match start {
+        0 => 0,
+        start => text[..floor_char_boundary(text, start)]
+            .rfind(' ')
+            .map(|i| i + 1)
+            .unwrap_or(0),
+    };
+    let end = floor_char_boundary(text, (start + SNIPPET_LENGTH).min(text.len()));
+    let end = match text[end..].find(' ') {
+        Some(i) => end + i,
+        None => text.len(),
+    };
+
+    let mut html = String::new();
+    if start > 0 {
+        html.push_str("… ");
+    }
+    let mut position = start;
+    for range in matches {
+        if range.start >= position && range.end <= end {
+            let highlighted = maud::html! {
+                (text[position..range.start])
+                mark { (text[range.clone()]) }
+            };
+            html.push_str(&highlighted.0);
+            position = range.end;
+        }
+    }
+    html.push_str(&maud::html! { (text[position..end]) }.0);
+    if end < text.len() {
+        html.push_str(" …");
+    }
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> SearchIndex {
+        let mut index = SearchIndex::default();
+        index.add(
+            DocumentKind::Post,
+            "/blog/nginx".to_owned(),
+            "Running xymon on nginx",
+            "Monitoring without apache",
+            "<p>Some notes about <code>fastcgi</code> &amp; friends.</p>",
+        );
+        index.add(
+            DocumentKind::Project,
+            "/projects/pronouns".to_owned(),
+            "Player pronouns",
+            "A mod",
+            "<p>Shows pronouns next to player names. Works with nginx too.</p>",
+        );
+        index.add(
+            DocumentKind::Post,
+            "/blog/draft".to_owned(),
+            "Unfinished nginx post",
+            "Not out yet",
+            "<p>nginx nginx nginx</p>",
+        );
+        index
+    }
+
+    fn urls(results: &[SearchResult]) -> Vec<&str> {
+        results.iter().map(|result| result.url.as_str()).collect()
+    }
+
+    #[test]
+    fn tokenize_lowercases_and_keeps_positions() {
+        let tokens = tokenize("Hello, wörld! 42").collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                (0..5, "hello".to_owned()),
+                (7..13, "wörld".to_owned()),
+                (15..17, "42".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn html_to_text_strips_tags_and_decodes_entities() {
+        assert_eq!(
+            html_to_text("<p>a &amp; <b>b</b>\n  &lt;c&gt;</p>"),
+            "a & b <c>"
+        );
+    }
+
+    #[test]
+    fn title_matches_rank_above_body_matches() {
+        let results = index().search("nginx", 10, |_| true);
+        assert_eq!(
+            urls(&results),
+            vec!["/blog/draft", "/blog/nginx", "/projects/pronouns"]
+        );
+    }
+
+    #[test]
+    fn hidden_documents_are_skipped() {
+        let results = index().search("nginx", 10, |url| url != "/blog/draft");
+        assert_eq!(urls(&results), vec!["/blog/nginx", "/projects/pronouns"]);
+    }
+
+    #[test]
+    fn documents_matching_more_terms_rank_higher() {
+        let results = index().search("pronouns nginx", 10, |_| true);
+        assert_eq!(results[0].url, "/projects/pronouns");
+    }
+
+    #[test]
+    fn limit_and_empty_queries() {
+        assert_eq!(index().search("nginx", 1, |_| true).len(), 1);
+        assert!(index().search("  !? ", 10, |_| true).is_empty());
+        assert!(index().search("nothing", 10, |_| true).is_empty());
+    }
+
+    #[test]
+    fn snippet_highlights_matches_and_escapes_text() {
+        let results = index().search("FastCGI", 10, |_| true);
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            results[0].snippet,
+            "Some notes about <mark>fastcgi</mark> &amp; friends."
+        );
+    }
+
+    #[test]
+    fn snippet_is_cut_on_word_boundaries() {
+        let text = format!("{} needle {}", "é ".repeat(100), "after ".repeat(100));
+        let snippet = snippet(&text, &["needle".to_owned()]);
+        assert!(snippet.starts_with("… é"));
+        assert!(snippet.ends_with(" …"));
+        assert!(snippet.contains("<mark>needle</mark>"));
+        assert!(snippet.len() < text.len());
+    }
+}
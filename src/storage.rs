@@ -0,0 +1,32 @@
+//! Keeps state that outlives the server, like followers and webmentions, in JSON files.
+
+use std::path::Path;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::Result;
+
+/// Reads a JSON file, returning `None` if it doesn't exist yet.
+pub async fn load_json<T: DeserializeOwned>(path: &Path) -> Result<Option<T>> {
+    match tokio::fs::read(path).await {
+        Ok(json) => Ok(Some(serde_json::from_slice(&json)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn save_json(path: &Path, value: &impl Serialize) -> Result<()> {
+    write_atomic(path, &serde_json::to_vec_pretty(value)?).await?;
+    Ok(())
+}
+
+/// Replaces a file all at once, so a crash can't leave a half-written file behind.
+///
+/// The temporary file is always the same one, so callers must make sure that only one write to
+/// each file happens at a time.
+pub async fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    tokio::fs::write(&temp, contents).await?;
+    tokio::fs::rename(&temp, path).await
+}
@@ -1,13 +1,13 @@
-use std::collections::HashMap;
+use std::{cell::RefCell, collections::HashMap, sync::Arc};
 
 use askama::Template;
 use axum::{
     http::StatusCode,
     response::{Html, IntoResponse},
 };
-use lol_html::{element, html_content::ContentType, rewrite_str, Settings};
+use lol_html::{element, html_content::ContentType, rewrite_str, text, Settings};
 use maud::PreEscaped;
-use time::{format_description::well_known::Rfc2822, OffsetDateTime};
+use time::OffsetDateTime;
 
 use crate::{
     apis::{
@@ -15,7 +15,11 @@ use crate::{
         NowPlayingInfo, PronounsPageCard,
     },
     assets::ASSET_INDEX,
+    content::Navigation,
+    markdown::ChangelogEntry,
     routes::blog::BlogPost,
+    search::{DocumentKind, SearchResult},
+    webmention::PostMentions,
 };
 
 macro_rules! simple_template {
@@ -45,31 +49,75 @@ pub struct MusicTemplate {
 
 #[derive(Template)]
 #[template(path = "blog-index.html", blocks = ["title", "description"])]
-pub struct BlogIndexTemplate {
-    pub posts: Vec<BlogPost>,
+pub struct BlogIndexTemplate<'a> {
+    pub posts: &'a [Arc<BlogPost>],
+    pub newer_page: Option<String>,
+    pub older_page: Option<String>,
 }
 
 #[derive(Template)]
 #[template(path = "blog-post.html", blocks = ["title", "description"])]
-pub struct BlogPostTemplate {
-    pub title: String,
+pub struct BlogPostTemplate<'a> {
+    pub title: &'a str,
     pub date: String,
-    pub description: String,
-    pub content: String,
+    /// Only set if the post has changed since it was posted.
+    pub updated: Option<String>,
+    pub changelog: &'a [ChangelogEntry],
+    pub description: &'a str,
+    pub url: &'a str,
+    pub tags: &'a [String],
+    /// The series this post is part of, and all of its published parts.
+    pub series: Option<(&'a str, &'a [Arc<BlogPost>])>,
+    pub word_count: usize,
+    pub reading_time: usize,
+    /// Whether this is a preview of a post that isn't published yet.
+    pub preview: bool,
+    /// Not available for previews.
+    pub navigation: Option<&'a Navigation>,
+    pub mentions: PostMentions,
+    pub comments: Option<Comments>,
+    pub content: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "blog-tags.html", blocks = ["title", "description"])]
+pub struct BlogTagsTemplate<'a> {
+    pub tags: Vec<(&'a str, usize)>,
+}
+
+#[derive(Template)]
+#[template(path = "blog-tag.html", blocks = ["title", "description"])]
+pub struct BlogTagTemplate<'a> {
+    pub tag: &'a str,
+    pub posts: &'a [Arc<BlogPost>],
+}
+
+#[derive(Template)]
+#[template(path = "blog-series.html", blocks = ["title", "description"])]
+pub struct BlogSeriesTemplate<'a> {
+    pub name: &'a str,
+    pub posts: &'a [Arc<BlogPost>],
 }
 
 #[derive(Template)]
 #[template(path = "projects.html", blocks = ["title", "description"])]
-pub struct ProjectsTemplate {
-    pub projects_by_year: Vec<(String, Vec<(String, String)>)>,
+pub struct ProjectsTemplate<'a> {
+    pub projects_by_year: Vec<(&'a str, Vec<(&'a str, String)>)>,
 }
 
 #[derive(Template)]
 #[template(path = "project.html", blocks = ["title", "description"])]
-pub struct ProjectTemplate {
-    pub title: String,
-    pub description: String,
-    pub content: String,
+pub struct ProjectTemplate<'a> {
+    pub title: &'a str,
+    pub description: &'a str,
+    pub content: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "search.html", blocks = ["title", "description"])]
+pub struct SearchTemplate {
+    pub query: String,
+    pub results: Vec<SearchResult>,
 }
 
 #[derive(Template)]
@@ -133,30 +181,79 @@ macro_rules! attr_rewrite {
 }
 
 async fn load_post(server: &str, id: &str) -> PostData {
-    let post = fedi::POST_FETCHER
-        .get_post(server.to_owned(), id.to_owned())
-        .await;
-    match post {
-        Ok(post) => post,
-        Err(e) => {
-            tracing::warn!(server, id, ?e, "failed to fetch post");
-            PostData {
-                url: "https://oopsie.ashhhleyyy.dev/".to_owned(),
-                content: "Failed to load toot!".to_owned(),
-                timestamps: fedi::Timestamps::Created {
-                    created_at: OffsetDateTime::UNIX_EPOCH,
-                },
-                account: AccountData {
-                    avatar_static:
-                        "https://cdn.ashhhleyyy.dev/file/ashhhleyyy-assets/images/pfp.png"
-                            .to_owned(),
-                    avatar: "https://cdn.ashhhleyyy.dev/file/ashhhleyyy-assets/images/pfp.png"
-                        .to_owned(),
-                    display_name: "Ashley".to_owned(),
-                    fqn: "ash@ashhhleyyy.dev".to_owned(),
-                    url: "https://ashhhleyyy.dev".to_owned(),
-                },
-                media_attachments: vec![],
+    match fedi::POST_FETCHER.get_post(server, id).await {
+        Some(post) => post,
+        None => PostData {
+            url: "https://oopsie.ashhhleyyy.dev/".to_owned(),
+            content: "Failed to load toot!".to_owned(),
+            timestamps: fedi::Timestamps::Created {
+                created_at: OffsetDateTime::UNIX_EPOCH,
+            },
+            account: AccountData {
+                avatar_static: "https://cdn.ashhhleyyy.dev/file/ashhhleyyy-assets/images/pfp.png"
+                    .to_owned(),
+                display_name: "Ashley".to_owned(),
+                fqn: "ash@ashhhleyyy.dev".to_owned(),
+                url: "https://ashhhleyyy.dev".to_owned(),
+            },
+            media_attachments: vec![],
+        },
+    }
+}
+
+/// Replies to the fediverse post announcing a blog post.
+pub struct Comments {
+    /// Where to reply to add a comment.
+    pub url: String,
+    pub replies: String,
+}
+
+/// Only uses what's already cached, so fetching replies never holds up a post. Anything
+/// missing is fetched in the background for later requests.
+pub fn load_comments(server: &str, id: &str) -> Option<Comments> {
+    let post = fedi::POST_FETCHER.cached(server, id);
+    let context = fedi::CONTEXT_FETCHER.cached(server, id);
+    let post = post?;
+    Some(Comments {
+        url: post.url,
+        replies: context
+            .map(|context| context.replies_html(id).0)
+            .unwrap_or_default(),
+    })
+}
+
+/// A heading found while rewriting a page, for building the `<toc>` element.
+struct TocHeading {
+    level: u8,
+    id: String,
+    /// Already HTML-escaped, as it came from the document.
+    text: String,
+}
+
+fn render_toc(headings: &[TocHeading]) -> maud::Markup {
+    // each heading owns the deeper headings that follow it, up until the next one at its level
+    let mut entries = vec![];
+    let mut i = 0;
+    while i < headings.len() {
+        let end = headings[i + 1..]
+            .iter()
+            .position(|heading| heading.level <= headings[i].level)
+            .map_or(headings.len(), |offset| i + 1 + offset);
+        entries.push((&headings[i], &headings[i + 1..end]));
+        i = end;
+    }
+
+    maud::html! {
+        ol {
+            @for (heading, children) in entries {
+                li {
+                    a href=(format!("#{}", heading.id)) {
+                        (PreEscaped(heading.text.trim()))
+                    }
+                    @if !children.is_empty() {
+                        (render_toc(children))
+                    }
+                }
             }
         }
     }
@@ -166,9 +263,11 @@ async fn load_post(server: &str, id: &str) -> PostData {
 pub(crate) async fn rewrite_html(path: &str, html: &str) -> String {
     let now = OffsetDateTime::now_utc();
 
-    // First pass to locate fedi posts and footnotes
+    // First pass to locate fedi posts, footnotes and headings
     let mut posts = vec![];
     let mut footnotes: Vec<Footnote> = vec![];
+    let headings: RefCell<Vec<TocHeading>> = RefCell::new(vec![]);
+    const HEADINGS: &str = "h1[id], h2[id], h3[id], h4[id], h5[id], h6[id]";
 
     struct Footnote {
         id: String,
@@ -204,6 +303,20 @@ pub(crate) async fn rewrite_html(path: &str, html: &str) -> String {
                 }
                 Ok(())
             }))
+            .append_element_content_handler(element!(HEADINGS, |el| {
+                headings.borrow_mut().push(TocHeading {
+                    level: el.tag_name()[1..].parse().unwrap_or(1),
+                    id: el.get_attribute("id").unwrap_or_default(),
+                    text: String::new(),
+                });
+                Ok(())
+            }))
+            .append_element_content_handler(text!(HEADINGS, |chunk| {
+                if let Some(heading) = headings.borrow_mut().last_mut() {
+                    heading.text.push_str(chunk.as_str());
+                }
+                Ok(())
+            }))
             .append_element_content_handler(element!("fedi-post", |el| {
                 if let (Some(server), Some(id)) =
                     (el.get_attribute("data-server"), el.get_attribute("data-id"))
@@ -223,9 +336,6 @@ pub(crate) async fn rewrite_html(path: &str, html: &str) -> String {
                             avatar_static:
                                 "https://cdn.ashhhleyyy.dev/file/ashhhleyyy-assets/images/pfp.png"
                                     .to_owned(),
-                            avatar:
-                                "https://cdn.ashhhleyyy.dev/file/ashhhleyyy-assets/images/pfp.png"
-                                    .to_owned(),
                             display_name: "Ashley".to_owned(),
                             fqn: "ash@ashhhleyyy.dev".to_owned(),
                             url: "https://ashhhleyyy.dev".to_owned(),
@@ -310,18 +420,13 @@ pub(crate) async fn rewrite_html(path: &str, html: &str) -> String {
         resolved_posts
     };
 
+    let headings = headings.into_inner();
+
     let settings = Settings::new()
         .append_element_content_handler(element!("copyright-year", |el| {
             el.replace(&format!("{}", now.year()), ContentType::Text);
             Ok(())
         }))
-        .append_element_content_handler(element!("page-generated", |el| {
-            el.replace(
-                &now.format(&Rfc2822).expect("failed to format"),
-                ContentType::Text,
-            );
-            Ok(())
-        }))
         .append_element_content_handler(element!("fedi-post", |el| {
             el.replace(&posts.get(&(el.get_attribute("data-server").unwrap(), el.get_attribute("data-id").unwrap())).unwrap().as_html().0, ContentType::Html);
             Ok(())
@@ -359,6 +464,18 @@ pub(crate) async fn rewrite_html(path: &str, html: &str) -> String {
             el.replace(&footnotes.0, ContentType::Html);
             Ok(())
         }))
+        .append_element_content_handler(element!("toc", |el| {
+            let toc = maud::html! {
+                @if !headings.is_empty() {
+                    details.toc open {
+                        summary { "Contents" }
+                        (render_toc(&headings))
+                    }
+                }
+            };
+            el.replace(&toc.0, ContentType::Html);
+            Ok(())
+        }))
         .append_element_content_handler(element!(".nav-link", |el| {
             if let Some(href) = el.get_attribute("href") {
                 let matches = if href == "/" {
@@ -418,7 +535,5 @@ pub(crate) async fn rewrite_html(path: &str, html: &str) -> String {
         Ok(())
     }));
 
-    
-
     rewrite_str(&html, settings).unwrap()
 }
@@ -0,0 +1,533 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use lol_html::{element, rewrite_str, text, RewriteStrSettings};
+use reqwest::{header::CONTENT_TYPE, Client, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tokio::sync::mpsc;
+
+use crate::{
+    apis::public::{is_public_url, PUBLIC_CLIENT},
+    error::{Result, WebsiteError},
+    search,
+    storage::{load_json, write_atomic},
+};
+
+/// How many webmentions can be waiting for verification before new ones are turned away.
+const QUEUE_SIZE: usize = 256;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Sources larger than this are only checked up to this point.
+const MAX_SOURCE_SIZE: usize = 1024 * 1024;
+/// Longer replies are cut short when shown under a post.
+const MAX_CONTENT_CHARS: usize = 280;
+/// New mentions of a post are ignored once it has this many, so the file can't be filled up.
+const MAX_MENTIONS_PER_POST: usize = 256;
+/// How many pages on one host can mention the same post.
+const MAX_MENTIONS_PER_HOST: usize = 10;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MentionKind {
+    Mention,
+    Repost,
+    Like,
+    Reply,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Author {
+    pub name: String,
+    pub url: Option<String>,
+}
+
+/// A webmention whose source has been checked to link to one of our posts.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Webmention {
+    pub source: String,
+    pub kind: MentionKind,
+    pub author: Option<Author>,
+    /// A plain text excerpt of the source, if it has a microformats `e-content`.
+    pub content: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub verified: OffsetDateTime,
+}
+
+impl Webmention {
+    /// The author's name, or the source's host if it doesn't say who wrote it.
+    pub fn author_name(&self) -> String {
+        match &self.author {
+            Some(author) => author.name.clone(),
+            None => Url::parse(&self.source)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_owned))
+                .unwrap_or_else(|| self.source.clone()),
+        }
+    }
+
+    pub fn author_url(&self) -> &str {
+        self.author
+            .as_ref()
+            .and_then(|author| author.url.as_deref())
+            .unwrap_or(&self.source)
+    }
+}
+
+/// The webmentions of a single post, oldest first.
+#[derive(Default)]
+pub struct PostMentions {
+    pub replies: Vec<Webmention>,
+    pub likes: Vec<Webmention>,
+    pub reposts: Vec<Webmention>,
+    pub mentions: Vec<Webmention>,
+}
+
+impl PostMentions {
+    pub fn is_empty(&self) -> bool {
+        self.replies.is_empty()
+            && self.likes.is_empty()
+            && self.reposts.is_empty()
+            && self.mentions.is_empty()
+    }
+}
+
+pub struct PendingMention {
+    pub source: Url,
+    pub target: Url,
+    /// The path of the post being mentioned, which mentions are stored under.
+    pub post: String,
+}
+
+/// Receives webmentions, verifies them in the background and keeps the verified ones on disk.
+pub struct Webmentions {
+    queue: mpsc::Sender<PendingMention>,
+    client: Client,
+    path: PathBuf,
+    /// Verified mentions, by the path of the post they mention.
+    mentions: RwLock<BTreeMap<String, Vec<Webmention>>>,
+}
+
+impl Webmentions {
+    /// Loads the mentions stored in `dir`, and starts verifying new ones.
+    pub async fn start(dir: PathBuf) -> Result<Arc<Self>> {
+        Self::start_with_client(dir, PUBLIC_CLIENT.clone()).await
+    }
+
+    async fn start_with_client(dir: PathBuf, client: Client) -> Result<Arc<Self>> {
+        tokio::fs::create_dir_all(&dir).await?;
+        let path = dir.join("webmentions.json");
+        let mentions = load_json(&path).await?.unwrap_or_default();
+
+        let (queue, mut pending) = mpsc::channel(QUEUE_SIZE);
+        let webmentions = Arc::new(Self {
+            queue,
+            client,
+            path,
+            mentions: RwLock::new(mentions),
+        });
+
+        let worker = webmentions.clone();
+        tokio::spawn(async move {
+            while let Some(mention) = pending.recv().await {
+                worker.process(mention).await;
+            }
+        });
+
+        Ok(webmentions)
+    }
+
+    /// Queues a mention for verification, returning false if the queue is full.
+    pub fn enqueue(&self, mention: PendingMention) -> bool {
+        self.queue.try_send(mention).is_ok()
+    }
+
+    pub fn for_post(&self, post: &str) -> PostMentions {
+        let mut post_mentions = PostMentions::default();
+        let mentions = self.mentions.read().unwrap();
+        for mention in mentions.get(post).into_iter().flatten() {
+            let list = match mention.kind {
+                MentionKind::Reply => &mut post_mentions.replies,
+                MentionKind::Like => &mut post_mentions.likes,
+                MentionKind::Repost => &mut post_mentions.reposts,
+                MentionKind::Mention => &mut post_mentions.mentions,
+            };
+            list.push(mention.clone());
+        }
+        post_mentions
+    }
+
+    async fn process(&self, pending: PendingMention) {
+        let verified = match verify(&self.client, &pending.source, &pending.target).await {
+            Ok(verified) => verified,
+            Err(e) => {
+                // the source might only be down for a moment, so keep any earlier mention
+                warn!(source = %pending.source, ?e, "failed to verify webmention");
+                return;
+            }
+        };
+
+        let json = {
+            let mut mentions = self.mentions.write().unwrap();
+            let post_mentions = mentions.entry(pending.post.clone()).or_default();
+            let source = pending.source.as_str();
+            let existing = post_mentions
+                .iter()
+                .position(|mention| mention.source == source);
+            let changed = match (verified, existing) {
+                (Some(mention), Some(i)) => {
+                    post_mentions[i] = mention;
+                    true
+                }
+                (Some(_), None) if is_full(post_mentions, &pending.source) => {
+                    warn!(
+                        source = %pending.source,
+                        post = pending.post,
+                        "too many webmentions, ignoring"
+                    );
+                    return;
+                }
+                (Some(mention), None) => {
+                    post_mentions.push(mention);
+                    true
+                }
+                // the source no longer links to the post, so the mention has been deleted
+                (None, Some(i)) => {
+                    post_mentions.remove(i);
+                    true
+                }
+                (None, None) => false,
+            };
+            if post_mentions.is_empty() {
+                mentions.remove(&pending.post);
+            }
+            if !changed {
+                info!(
+                    source = %pending.source,
+                    post = pending.post,
+                    "webmention source doesn't link to post"
+                );
+                return;
+            }
+            serde_json::to_vec_pretty(&*mentions).expect("failed to serialize webmentions")
+        };
+
+        // mentions are only processed by one task, so saves can't overlap
+        if let Err(e) = write_atomic(&self.path, &json).await {
+            error!(?e, "failed to save webmentions");
+        }
+    }
+}
+
+/// Whether a post has as many mentions as it can take, either in total or from `source`'s host.
+fn is_full(mentions: &[Webmention], source: &Url) -> bool {
+    let from_host = mentions
+        .iter()
+        .filter(|mention| {
+            Url::parse(&mention.source).is_ok_and(|url| url.host_str() == source.host_str())
+        })
+        .count();
+    mentions.len() >= MAX_MENTIONS_PER_POST || from_host >= MAX_MENTIONS_PER_HOST
+}
+
+/// Fetches `source` and checks that it links to `target`. Returns `None` if it doesn't, or
+/// if it has been deleted.
+async fn verify(client: &Client, source: &Url, target: &Url) -> Result<Option<Webmention>> {
+    // addresses don't go through the client's resolver, so they're checked here
+    if !is_public_url(source) {
+        return Err(WebsiteError::PrivateUrl);
+    }
+    let response = client
+        .get(source.clone())
+        .timeout(FETCH_TIMEOUT)
+        .send()
+        .await?;
+    if response.status() == StatusCode::GONE {
+        return Ok(None);
+    }
+    let mut response = response.error_for_status()?;
+    let is_html = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.contains("html"));
+
+    let mut body = vec![];
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() >= MAX_SOURCE_SIZE {
+            body.truncate(MAX_SOURCE_SIZE);
+            break;
+        }
+    }
+    let body = String::from_utf8_lossy(&body);
+
+    let mention = if is_html {
+        parse_mention(&body, source, target)
+    } else {
+        body.contains(target.as_str())
+            .then_some((MentionKind::Mention, None, None))
+    };
+    Ok(mention.map(|(kind, author, content)| Webmention {
+        source: source.to_string(),
+        kind,
+        author,
+        content,
+        verified: OffsetDateTime::now_utc(),
+    }))
+}
+
+/// Resolves a link in the source, ignoring any fragment, and only allowing web URLs.
+fn resolve(source: &Url, href: &str) -> Option<Url> {
+    let mut url = source.join(href).ok()?;
+    url.set_fragment(None);
+    matches!(url.scheme(), "http" | "https").then_some(url)
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    search::unescape(text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Looks for a link to `target` in the HTML of a source, and uses its microformats to work
+/// out what kind of mention it is and who it's from.
+fn parse_mention(
+    html: &str,
+    source: &Url,
+    target: &Url,
+) -> Option<(MentionKind, Option<Author>, Option<String>)> {
+    let kind = RefCell::new(None::<MentionKind>);
+    let mut author_name = String::new();
+    let mut author_fallback = String::new();
+    let mut author_url = None;
+    let mut content = String::new();
+
+    let _ = rewrite_str(
+        html,
+        RewriteStrSettings::new()
+            .append_element_content_handler(element!("a[href], link[href]", |el| {
+                let href = el.get_attribute("href").unwrap_or_default();
+                if resolve(source, &href).as_ref() != Some(target) {
+                    return Ok(());
+                }
+                let class = el.get_attribute("class").unwrap_or_default();
+                let found = class
+                    .split_whitespace()
+                    .find_map(|class| match class {
+                        "u-in-reply-to" => Some(MentionKind::Reply),
+                        "u-like-of" => Some(MentionKind::Like),
+                        "u-repost-of" => Some(MentionKind::Repost),
+                        _ => None,
+                    })
+                    .unwrap_or(MentionKind::Mention);
+                let mut kind = kind.borrow_mut();
+                *kind = Some(kind.map_or(found, |kind| kind.max(found)));
+                Ok(())
+            }))
+            .append_element_content_handler(element!(
+                ".p-author[href], .p-author .u-url[href]",
+                |el| {
+                    if author_url.is_none() {
+                        author_url = el
+                            .get_attribute("href")
+                            .and_then(|href| resolve(source, &href));
+                    }
+                    Ok(())
+                }
+            ))
+            .append_element_content_handler(text!(".p-author .p-name", |chunk| {
+                author_name.push_str(chunk.as_str());
+                Ok(())
+            }))
+            .append_element_content_handler(text!(".p-author", |chunk| {
+                author_fallback.push_str(chunk.as_str());
+                Ok(())
+            }))
+            .append_element_content_handler(text!(".e-content", |chunk| {
+                content.push_str(chunk.as_str());
+                Ok(())
+            })),
+    );
+
+    let kind = kind.into_inner()?;
+    let author_name = Some(collapse_whitespace(&author_name))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| collapse_whitespace(&author_fallback));
+    let author = (!author_name.is_empty()).then(|| Author {
+        name: author_name,
+        url: author_url.map(String::from),
+    });
+
+    let content = collapse_whitespace(&content);
+    let content = match content.char_indices().nth(MAX_CONTENT_CHARS) {
+        Some((end, _)) => Some(format!("{}…", &content[..end])),
+        None => (!content.is_empty()).then_some(content),
+    };
+
+    Some((kind, author, content))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use axum::{extract::State, http::header, response::IntoResponse, routing::get, Router};
+
+    use super::*;
+
+    const TARGET: &str = "https://ashhhleyyy.dev/blog/post";
+    const POST: &str = "/blog/post";
+
+    fn reply() -> String {
+        format!(
+            r#"<article class="h-entry">
+                <a class="p-author h-card" href="/"><span class="p-name">Someone</span></a>
+                <a class="u-in-reply-to" href="{TARGET}">in reply to</a>
+                <p class="e-content">Nice &amp; short</p>
+            </article>"#
+        )
+    }
+
+    /// Serves sources for the tests on localhost, returning its URL.
+    async fn stand_in(linked: Arc<AtomicBool>) -> Url {
+        async fn html(body: String) -> impl IntoResponse {
+            ([(header::CONTENT_TYPE, "text/html")], body)
+        }
+
+        let app = Router::new()
+            .route("/valid", get(|| html(reply())))
+            .route(
+                "/missing",
+                get(|| html("<p>nothing to see here</p>".into())),
+            )
+            .route(
+                "/oversized",
+                get(|| html(format!("{}{}", " ".repeat(MAX_SOURCE_SIZE), reply()))),
+            )
+            .route(
+                "/changing",
+                get(|State(linked): State<Arc<AtomicBool>>| async move {
+                    if linked.load(Ordering::SeqCst) {
+                        html(reply()).await.into_response()
+                    } else {
+                        StatusCode::GONE.into_response()
+                    }
+                }),
+            )
+            .with_state(linked);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        // a hostname, as the resolver is what stops the real client from connecting to it
+        Url::parse(&format!("http://localhost:{}/", addr.port())).unwrap()
+    }
+
+    async fn webmentions(name: &str) -> Arc<Webmentions> {
+        let dir = std::env::temp_dir().join(format!("webmentions-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        Webmentions::start_with_client(dir, Client::new())
+            .await
+            .unwrap()
+    }
+
+    async fn send(webmentions: &Webmentions, source: Url) {
+        webmentions
+            .process(PendingMention {
+                source,
+                target: Url::parse(TARGET).unwrap(),
+                post: POST.to_owned(),
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn valid_reply_is_stored() {
+        let server = stand_in(Default::default()).await;
+        let webmentions = webmentions("valid").await;
+        send(&webmentions, server.join("valid").unwrap()).await;
+
+        let mentions = webmentions.for_post(POST);
+        assert_eq!(mentions.replies.len(), 1);
+        let reply = &mentions.replies[0];
+        assert_eq!(reply.author_name(), "Someone");
+        assert_eq!(reply.author_url(), server.as_str());
+        assert_eq!(reply.content.as_deref(), Some("Nice & short"));
+
+        let saved = std::fs::read_to_string(&webmentions.path).unwrap();
+        assert!(saved.contains("/valid"));
+    }
+
+    #[tokio::test]
+    async fn source_without_link_is_ignored() {
+        let server = stand_in(Default::default()).await;
+        let webmentions = webmentions("missing").await;
+        send(&webmentions, server.join("missing").unwrap()).await;
+        assert!(webmentions.for_post(POST).is_empty());
+    }
+
+    #[tokio::test]
+    async fn link_past_size_limit_is_not_found() {
+        let server = stand_in(Default::default()).await;
+        let webmentions = webmentions("oversized").await;
+        send(&webmentions, server.join("oversized").unwrap()).await;
+        assert!(webmentions.for_post(POST).is_empty());
+    }
+
+    #[tokio::test]
+    async fn deleted_source_removes_mention() {
+        let linked = Arc::new(AtomicBool::new(true));
+        let server = stand_in(linked.clone()).await;
+        let webmentions = webmentions("deleted").await;
+        let source = server.join("changing").unwrap();
+
+        send(&webmentions, source.clone()).await;
+        assert_eq!(webmentions.for_post(POST).replies.len(), 1);
+
+        linked.store(false, Ordering::SeqCst);
+        send(&webmentions, source).await;
+        assert!(webmentions.for_post(POST).is_empty());
+        assert!(!std::fs::read_to_string(&webmentions.path)
+            .unwrap()
+            .contains("/changing"));
+    }
+
+    #[tokio::test]
+    async fn mentions_from_one_host_are_capped() {
+        let server = stand_in(Default::default()).await;
+        let webmentions = webmentions("capped").await;
+        for i in 0..MAX_MENTIONS_PER_HOST + 2 {
+            send(&webmentions, server.join(&format!("valid?{i}")).unwrap()).await;
+        }
+        assert_eq!(
+            webmentions.for_post(POST).replies.len(),
+            MAX_MENTIONS_PER_HOST
+        );
+    }
+
+    #[tokio::test]
+    async fn private_sources_are_not_fetched() {
+        let server = stand_in(Default::default()).await;
+        let webmentions = webmentions("private").await;
+        let result = verify(
+            &PUBLIC_CLIENT,
+            &server.join("valid").unwrap(),
+            &Url::parse(TARGET).unwrap(),
+        )
+        .await;
+        assert!(result.is_err());
+        let result = verify(
+            &Client::new(),
+            &Url::parse("http://127.0.0.1/").unwrap(),
+            &Url::parse(TARGET).unwrap(),
+        )
+        .await;
+        assert!(matches!(result, Err(WebsiteError::PrivateUrl)));
+        assert!(webmentions.for_post(POST).is_empty());
+    }
+}